@@ -0,0 +1,245 @@
+//! A map with one value per type, keyed by the type.
+
+use alloc::boxed::Box;
+use core::{
+    any::{Any, TypeId},
+    fmt::{self, Debug},
+    hash::{BuildHasherDefault, Hasher},
+    marker::PhantomData,
+};
+
+use crate::hash_map::HashMap;
+
+/// A hasher for `TypeId`s, which are already hashes. It just folds what it's given together.
+/// Don't use it for anything else.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TypeIdHasher {
+    hash: u64,
+}
+
+impl Hasher for TypeIdHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut buf = [0; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(buf));
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.hash ^= n;
+    }
+
+    fn write_u128(&mut self, n: u128) {
+        self.write_u64(n as u64 ^ (n >> 64) as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+pub type TypeIdBuildHasher = BuildHasherDefault<TypeIdHasher>;
+
+/// The trait objects an `AnyMap` can hold: `dyn Any`, `dyn Any + Send` and
+/// `dyn Any + Send + Sync`.
+pub trait Downcast {
+    fn downcast_ref<T: Any>(&self) -> Option<&T>;
+    fn downcast_mut<T: Any>(&mut self) -> Option<&mut T>;
+    fn downcast<T: Any>(self: Box<Self>) -> Result<Box<T>, Box<Self>>;
+}
+
+/// Values that can be put into an `AnyMap<A>`.
+pub trait IntoBox<A: ?Sized + Downcast>: Any {
+    fn into_box(self) -> Box<A>;
+}
+
+macro_rules! impl_downcast {
+    ($($bounds:tt)*) => {
+        impl Downcast for dyn Any $($bounds)* {
+            fn downcast_ref<T: Any>(&self) -> Option<&T> {
+                <dyn Any>::downcast_ref(self)
+            }
+
+            fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+                <dyn Any>::downcast_mut(self)
+            }
+
+            fn downcast<T: Any>(self: Box<Self>) -> Result<Box<T>, Box<Self>> {
+                <Box<dyn Any $($bounds)*>>::downcast(self)
+            }
+        }
+
+        impl<T: Any $($bounds)*> IntoBox<dyn Any $($bounds)*> for T {
+            fn into_box(self) -> Box<dyn Any $($bounds)*> {
+                Box::new(self)
+            }
+        }
+    };
+}
+
+impl_downcast!();
+impl_downcast!(+ Send);
+impl_downcast!(+ Send + Sync);
+
+/// Holds at most one value of every type, e.g. per-plugin state. `A` is the trait object the
+/// values are stored as, `SendAnyMap` and `SyncAnyMap` only take values that are `Send` (and
+/// `Sync`) and are `Send` (and `Sync`) themselves.
+pub struct AnyMap<A: ?Sized + Downcast = dyn Any> {
+    map: HashMap<TypeId, Box<A>, TypeIdBuildHasher>,
+}
+
+pub type SendAnyMap = AnyMap<dyn Any + Send>;
+pub type SyncAnyMap = AnyMap<dyn Any + Send + Sync>;
+
+impl<A: ?Sized + Downcast> Debug for AnyMap<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AnyMap")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A: ?Sized + Downcast> Default for AnyMap<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: ?Sized + Downcast> AnyMap<A> {
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::with_hasher(TypeIdBuildHasher::default()),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity_and_hasher(capacity, TypeIdBuildHasher::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Returns the old value of the type.
+    pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
+        let old = self.map.insert(TypeId::of::<T>(), value.into_box())?;
+        Some(*old.downcast().ok().unwrap())
+    }
+
+    pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+        let value = self.map.get(&TypeId::of::<T>())?;
+        Some(value.downcast_ref().unwrap())
+    }
+
+    pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+        let value = self.map.get_mut(&TypeId::of::<T>())?;
+        Some(value.downcast_mut().unwrap())
+    }
+
+    pub fn contains<T: IntoBox<A>>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+        let value = self.map.remove(&TypeId::of::<T>())?;
+        Some(*value.downcast().ok().unwrap())
+    }
+
+    pub fn entry<T: IntoBox<A>>(&mut self) -> Entry<'_, A, T> {
+        if self.contains::<T>() {
+            Entry::Occupied(OccupiedEntry {
+                map: self,
+                _marker: PhantomData,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                map: self,
+                _marker: PhantomData,
+            })
+        }
+    }
+}
+
+/// The place of the value of type `T` in an `AnyMap`, see `AnyMap::entry`.
+pub enum Entry<'a, A: ?Sized + Downcast, T> {
+    Occupied(OccupiedEntry<'a, A, T>),
+    Vacant(VacantEntry<'a, A, T>),
+}
+
+impl<'a, A: ?Sized + Downcast, T: IntoBox<A>> Entry<'a, A, T> {
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> T>(self, default: F) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut T
+    where
+        T: Default,
+    {
+        self.or_insert_with(T::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut T)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+pub struct OccupiedEntry<'a, A: ?Sized + Downcast, T> {
+    map: &'a mut AnyMap<A>,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, A: ?Sized + Downcast, T: IntoBox<A>> OccupiedEntry<'a, A, T> {
+    pub fn get(&self) -> &T {
+        self.map.get().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.map.get_mut().unwrap()
+    }
+
+    pub fn into_mut(self) -> &'a mut T {
+        self.map.get_mut().unwrap()
+    }
+
+    /// Returns the old value.
+    pub fn insert(&mut self, value: T) -> T {
+        self.map.insert(value).unwrap()
+    }
+
+    pub fn remove(self) -> T {
+        self.map.remove().unwrap()
+    }
+}
+
+pub struct VacantEntry<'a, A: ?Sized + Downcast, T> {
+    map: &'a mut AnyMap<A>,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, A: ?Sized + Downcast, T: IntoBox<A>> VacantEntry<'a, A, T> {
+    pub fn insert(self, value: T) -> &'a mut T {
+        self.map.insert(value);
+        self.map.get_mut().unwrap()
+    }
+}
@@ -0,0 +1,307 @@
+//! Fixed-capacity maps and sets that never allocate, e.g. for interrupt handlers.
+//!
+//! They use the same buckets and collision arena as `HashMap`, but backed by inline arrays. Since
+//! the map never holds more than `N` entries, `N` slots are always enough for the colliding ones.
+
+use core::{
+    array,
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+};
+
+use crate::{
+    hash_map::{self, bucket_index, Arena, Bucket, DefaultHashBuilder, Slot},
+    hash_set,
+};
+
+#[derive(Clone)]
+pub struct ArrayHashMap<K, V, const N: usize, S = DefaultHashBuilder> {
+    hash_builder: S,
+    buckets: [Bucket<K, V>; N],
+    arena: Arena<K, V, [Slot<K, V>; N]>,
+    len: usize,
+}
+
+impl<K, V, const N: usize, S> Debug for ArrayHashMap<K, V, N, S>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, const N: usize> ArrayHashMap<K, V, N, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, const N: usize, S> ArrayHashMap<K, V, N, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            hash_builder,
+            buckets: array::from_fn(|_| Bucket::default()),
+            arena: Arena::vacant(),
+            len: 0,
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, K, V> {
+        self.into_iter()
+    }
+
+    pub fn iter_mut(&mut self) -> hash_map::IterMut<'_, K, V> {
+        self.into_iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.fill_with(Bucket::default);
+        self.arena = Arena::vacant();
+        self.len = 0;
+    }
+}
+
+impl<K, V, const N: usize, S> Default for ArrayHashMap<K, V, N, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, const N: usize, S> ArrayHashMap<K, V, N, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn index(&self, key: &K) -> Option<usize> {
+        bucket_index(&self.hash_builder, 0, key, N)
+    }
+
+    pub fn get_kv<'a>(&'a self, key: &K) -> Option<(&'a K, &'a V)> {
+        let idx = self.index(key)?;
+        self.buckets[idx].get(&self.arena, key)
+    }
+
+    pub fn get<'a>(&'a self, key: &K) -> Option<&'a V> {
+        self.get_kv(key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut_kv<'a>(&'a mut self, key: &K) -> Option<(&'a mut K, &'a mut V)> {
+        let idx = self.index(key)?;
+        self.buckets[idx].get_mut(&mut self.arena, key)
+    }
+
+    pub fn get_mut<'a>(&'a mut self, key: &K) -> Option<&'a mut V> {
+        self.get_mut_kv(key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_kv(key).is_some()
+    }
+
+    /// Returns the old entry if the key was already in the map.
+    /// Returns `Err` with the new entry if the key wasn't in the map and the map is full.
+    pub fn insert_kv(&mut self, key: K, value: V) -> Result<Option<(K, V)>, (K, V)> {
+        if self.is_full() && !self.contains_key(&key) {
+            return Err((key, value));
+        }
+        let Some(idx) = self.index(&key) else {
+            return Err((key, value));
+        };
+        let old = self.buckets[idx].insert(&mut self.arena, None, key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        Ok(old)
+    }
+
+    /// Returns the old value if the key was already in the map.
+    /// Returns `Err` with the new entry if the key wasn't in the map and the map is full.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, (K, V)> {
+        self.insert_kv(key, value).map(|old| old.map(|(_, v)| v))
+    }
+
+    pub fn remove_kv(&mut self, key: &K) -> Option<(K, V)> {
+        let idx = self.index(key)?;
+        let kv = self.buckets[idx].remove(&mut self.arena, key)?;
+        self.len -= 1;
+        Some(kv)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_kv(key).map(|(_, v)| v)
+    }
+}
+
+impl<'a, K, V, const N: usize, S> IntoIterator for &'a ArrayHashMap<K, V, N, S> {
+    type Item = (&'a K, &'a V);
+
+    type IntoIter = hash_map::Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        hash_map::Iter::new(&self.buckets, self.arena.slots())
+    }
+}
+
+impl<'a, K, V, const N: usize, S> IntoIterator for &'a mut ArrayHashMap<K, V, N, S> {
+    type Item = (&'a mut K, &'a mut V);
+
+    type IntoIter = hash_map::IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        hash_map::IterMut::new(&mut self.buckets, self.arena.slots_mut())
+    }
+}
+
+impl<K, V, const N: usize, S> IntoIterator for ArrayHashMap<K, V, N, S> {
+    type Item = (K, V);
+
+    type IntoIter = IntoIter<K, V, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            buckets: self.buckets.into_iter(),
+            slots: self.arena.into_slots().into_iter(),
+        }
+    }
+}
+
+pub struct IntoIter<K, V, const N: usize> {
+    buckets: array::IntoIter<Bucket<K, V>, N>,
+    slots: array::IntoIter<Slot<K, V>, N>,
+}
+
+impl<K, V, const N: usize> Iterator for IntoIter<K, V, N> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buckets
+            .by_ref()
+            .find_map(Bucket::into_first)
+            .or_else(|| self.slots.find_map(Slot::into_entry))
+    }
+}
+
+/// A fixed-capacity set, see `ArrayHashMap`.
+#[derive(Clone)]
+pub struct ArrayHashSet<T, const N: usize, S = DefaultHashBuilder> {
+    map: ArrayHashMap<T, (), N, S>,
+}
+
+impl<T: Debug, const N: usize, S> Debug for ArrayHashSet<T, N, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, const N: usize> ArrayHashSet<T, N, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self {
+            map: ArrayHashMap::new(),
+        }
+    }
+}
+
+impl<T, const N: usize, S> ArrayHashSet<T, N, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: ArrayHashMap::with_hasher(hash_builder),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.map.is_full()
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn iter(&self) -> hash_set::Iter<'_, T> {
+        hash_set::Iter {
+            inner: self.map.iter(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl<T, const N: usize, S> Default for ArrayHashSet<T, N, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<T, const N: usize, S> ArrayHashSet<T, N, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn get<'a>(&'a self, key: &T) -> Option<&'a T> {
+        self.map.get_kv(key).map(|(k, ())| k)
+    }
+
+    pub fn contains(&self, key: &T) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Returns the old key if it was already in the set.
+    /// Returns `Err` with the new key if it wasn't in the set and the set is full.
+    pub fn insert(&mut self, key: T) -> Result<Option<T>, T> {
+        match self.map.insert_kv(key, ()) {
+            Ok(old) => Ok(old.map(|(k, ())| k)),
+            Err((k, ())) => Err(k),
+        }
+    }
+
+    pub fn remove(&mut self, key: &T) -> Option<T> {
+        self.map.remove_kv(key).map(|(k, ())| k)
+    }
+}
+
+impl<'a, T, const N: usize, S> IntoIterator for &'a ArrayHashSet<T, N, S> {
+    type Item = &'a T;
+    type IntoIter = hash_set::Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,288 @@
+//! A one-to-one map that can be looked up from either side.
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    slice,
+};
+
+use crate::{hash_map::DefaultHashBuilder, raw_index::RawIndex};
+
+/// Every left value maps to one right value and the other way around. The pairs are stored once,
+/// in a dense `Vec`, with a hash table of positions for each side (like `IndexMap`), so the two
+/// directions can't drift apart.
+#[derive(Clone)]
+pub struct BiMap<L, R, S = DefaultHashBuilder> {
+    hash_builder: S,
+    entries: Vec<Entry<L, R>>,
+    by_left: RawIndex,
+    by_right: RawIndex,
+}
+
+#[derive(Clone)]
+struct Entry<L, R> {
+    left_hash: u64,
+    right_hash: u64,
+    left: L,
+    right: R,
+}
+
+/// The pairs that `BiMap::insert` removed to keep the map one-to-one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Overwritten<L, R> {
+    /// Neither value was in the map.
+    Neither,
+    /// The same pair was in the map.
+    Pair(L, R),
+    /// The pair with the same left value.
+    Left(L, R),
+    /// The pair with the same right value.
+    Right(L, R),
+    /// The pair with the same left value, then the one with the same right value.
+    Both((L, R), (L, R)),
+}
+
+impl<L, R> Overwritten<L, R> {
+    pub fn did_overwrite(&self) -> bool {
+        !matches!(self, Overwritten::Neither)
+    }
+}
+
+impl<L: Debug, R: Debug, S> Debug for BiMap<L, R, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<L, R> BiMap<L, R, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl<L, R, S> BiMap<L, R, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            hash_builder,
+            entries: Vec::with_capacity(capacity),
+            by_left: RawIndex::with_capacity(capacity),
+            by_right: RawIndex::with_capacity(capacity),
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.by_left.clear();
+        self.by_right.clear();
+    }
+
+    pub fn iter(&self) -> Iter<'_, L, R> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    /// Removes the pair at `idx` and moves the last one into its place.
+    fn swap_remove_index(&mut self, idx: usize) -> (L, R) {
+        let last = self.entries.len() - 1;
+        let entry = &self.entries[idx];
+        self.by_left.remove(entry.left_hash, idx);
+        self.by_right.remove(entry.right_hash, idx);
+        if idx != last {
+            let moved = &self.entries[last];
+            self.by_left.replace(moved.left_hash, last, idx);
+            self.by_right.replace(moved.right_hash, last, idx);
+        }
+        let entry = self.entries.swap_remove(idx);
+        (entry.left, entry.right)
+    }
+}
+
+impl<L, R, S> Default for BiMap<L, R, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<L, R, S> BiMap<L, R, S>
+where
+    L: Hash + Eq,
+    R: Hash + Eq,
+    S: BuildHasher,
+{
+    fn find_left(&self, hash: u64, left: &L) -> Option<usize> {
+        self.by_left.find(hash, |i| self.entries[i].left == *left)
+    }
+
+    fn find_right(&self, hash: u64, right: &R) -> Option<usize> {
+        self.by_right
+            .find(hash, |i| self.entries[i].right == *right)
+    }
+
+    pub fn get_by_left(&self, left: &L) -> Option<&R> {
+        let idx = self.find_left(self.hash_builder.hash_one(left), left)?;
+        Some(&self.entries[idx].right)
+    }
+
+    pub fn get_by_right(&self, right: &R) -> Option<&L> {
+        let idx = self.find_right(self.hash_builder.hash_one(right), right)?;
+        Some(&self.entries[idx].left)
+    }
+
+    pub fn contains_left(&self, left: &L) -> bool {
+        self.get_by_left(left).is_some()
+    }
+
+    pub fn contains_right(&self, right: &R) -> bool {
+        self.get_by_right(right).is_some()
+    }
+
+    pub fn remove_by_left(&mut self, left: &L) -> Option<(L, R)> {
+        let idx = self.find_left(self.hash_builder.hash_one(left), left)?;
+        Some(self.swap_remove_index(idx))
+    }
+
+    pub fn remove_by_right(&mut self, right: &R) -> Option<(L, R)> {
+        let idx = self.find_right(self.hash_builder.hash_one(right), right)?;
+        Some(self.swap_remove_index(idx))
+    }
+
+    /// Inserts the pair, removing the pairs that had either value.
+    pub fn insert(&mut self, left: L, right: R) -> Overwritten<L, R> {
+        let (left_hash, right_hash) = (
+            self.hash_builder.hash_one(&left),
+            self.hash_builder.hash_one(&right),
+        );
+        let overwritten = match (
+            self.find_left(left_hash, &left),
+            self.find_right(right_hash, &right),
+        ) {
+            (None, None) => Overwritten::Neither,
+            (Some(i), Some(j)) if i == j => {
+                let (l, r) = self.swap_remove_index(i);
+                Overwritten::Pair(l, r)
+            }
+            (Some(i), None) => {
+                let (l, r) = self.swap_remove_index(i);
+                Overwritten::Left(l, r)
+            }
+            (None, Some(j)) => {
+                let (l, r) = self.swap_remove_index(j);
+                Overwritten::Right(l, r)
+            }
+            (Some(i), Some(j)) => {
+                // The later one first, so the other one doesn't move.
+                let (by_left, by_right) = if i > j {
+                    let by_left = self.swap_remove_index(i);
+                    (by_left, self.swap_remove_index(j))
+                } else {
+                    let by_right = self.swap_remove_index(j);
+                    (self.swap_remove_index(i), by_right)
+                };
+                Overwritten::Both(by_left, by_right)
+            }
+        };
+        let idx = self.entries.len();
+        self.by_left.insert(left_hash, idx);
+        self.by_right.insert(right_hash, idx);
+        self.entries.push(Entry {
+            left_hash,
+            right_hash,
+            left,
+            right,
+        });
+        overwritten
+    }
+
+    /// Inserts the pair only if neither value is in the map, otherwise returns it.
+    pub fn insert_no_overwrite(&mut self, left: L, right: R) -> Result<(), (L, R)> {
+        if self.contains_left(&left) || self.contains_right(&right) {
+            return Err((left, right));
+        }
+        self.insert(left, right);
+        Ok(())
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional);
+        self.by_left.reserve(additional);
+        self.by_right.reserve(additional);
+    }
+}
+
+impl<L, R, S> FromIterator<(L, R)> for BiMap<L, R, S>
+where
+    L: Hash + Eq,
+    R: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (L, R)>>(iter: T) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<L, R, S> Extend<(L, R)> for BiMap<L, R, S>
+where
+    L: Hash + Eq,
+    R: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (L, R)>>(&mut self, iter: T) {
+        for (l, r) in iter {
+            self.insert(l, r);
+        }
+    }
+}
+
+impl<'a, L, R, S> IntoIterator for &'a BiMap<L, R, S> {
+    type Item = (&'a L, &'a R);
+    type IntoIter = Iter<'a, L, R>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Clone)]
+pub struct Iter<'a, L, R> {
+    inner: slice::Iter<'a, Entry<L, R>>,
+}
+
+impl<'a, L, R> Iterator for Iter<'a, L, R> {
+    type Item = (&'a L, &'a R);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|e| (&e.left, &e.right))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<L, R> ExactSizeIterator for Iter<'_, L, R> {}
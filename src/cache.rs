@@ -0,0 +1,405 @@
+//! A cache that holds a bounded number (or total weight) of entries and evicts the rest.
+
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+use core::{
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+};
+
+use crate::{hash_map::DefaultHashBuilder, index_map::IndexMap};
+
+/// Decides which entry a `BoundedCache` evicts. The cache keeps its entries at the positions
+/// `0..len` and tells the policy what happens to them, the policy only deals with positions.
+pub trait EvictionPolicy {
+    /// A new entry at `idx`, which is the number of entries before it.
+    fn insert(&mut self, idx: usize);
+    /// The entry at `idx` was read with `get` or `get_mut`.
+    fn access(&mut self, idx: usize);
+    /// The entry at `idx` is removed and the last entry moves into its place, like
+    /// `Vec::swap_remove`.
+    fn swap_remove(&mut self, idx: usize);
+    /// The entry to evict next, `None` if there are none.
+    fn victim(&self) -> Option<usize>;
+    fn clear(&mut self);
+}
+
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    prev: usize,
+    next: usize,
+}
+
+/// A doubly linked list of positions, newest first.
+#[derive(Debug, Clone)]
+struct List {
+    links: Vec<Link>,
+    head: usize,
+    tail: usize,
+}
+
+impl Default for List {
+    fn default() -> Self {
+        Self {
+            links: Vec::new(),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+}
+
+impl List {
+    fn link_front(&mut self, idx: usize) {
+        self.links[idx] = Link {
+            prev: NIL,
+            next: self.head,
+        };
+        match self.head {
+            NIL => self.tail = idx,
+            head => self.links[head].prev = idx,
+        }
+        self.head = idx;
+    }
+
+    fn unlink(&mut self, idx: usize) {
+        let Link { prev, next } = self.links[idx];
+        match prev {
+            NIL => self.head = next,
+            prev => self.links[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.links[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, idx: usize) {
+        debug_assert_eq!(idx, self.links.len());
+        self.links.push(Link {
+            prev: NIL,
+            next: NIL,
+        });
+        self.link_front(idx);
+    }
+
+    fn move_to_front(&mut self, idx: usize) {
+        if self.head != idx {
+            self.unlink(idx);
+            self.link_front(idx);
+        }
+    }
+
+    fn swap_remove(&mut self, idx: usize) {
+        self.unlink(idx);
+        let last = self.links.len() - 1;
+        if idx != last {
+            // Puts `last` where `idx` was in the list.
+            let Link { prev, next } = self.links[last];
+            self.links[idx] = self.links[last];
+            match prev {
+                NIL => self.head = idx,
+                prev => self.links[prev].next = idx,
+            }
+            match next {
+                NIL => self.tail = idx,
+                next => self.links[next].prev = idx,
+            }
+        }
+        self.links.pop();
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Evicts the least recently used entry.
+#[derive(Debug, Clone, Default)]
+pub struct Lru {
+    list: List,
+}
+
+impl EvictionPolicy for Lru {
+    fn insert(&mut self, idx: usize) {
+        self.list.push_front(idx);
+    }
+
+    fn access(&mut self, idx: usize) {
+        self.list.move_to_front(idx);
+    }
+
+    fn swap_remove(&mut self, idx: usize) {
+        self.list.swap_remove(idx);
+    }
+
+    fn victim(&self) -> Option<usize> {
+        Some(self.list.tail).filter(|&idx| idx != NIL)
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+    }
+}
+
+/// Evicts the oldest entry, reads don't matter.
+#[derive(Debug, Clone, Default)]
+pub struct Fifo {
+    list: List,
+}
+
+impl EvictionPolicy for Fifo {
+    fn insert(&mut self, idx: usize) {
+        self.list.push_front(idx);
+    }
+
+    fn access(&mut self, _: usize) {}
+
+    fn swap_remove(&mut self, idx: usize) {
+        self.list.swap_remove(idx);
+    }
+
+    fn victim(&self) -> Option<usize> {
+        Some(self.list.tail).filter(|&idx| idx != NIL)
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+    }
+}
+
+/// Evicts the least frequently used entry, and the least recently used of those on a tie.
+/// Operations are O(log n). Counts never decay, so an entry that was popular long ago stays.
+#[derive(Debug, Clone, Default)]
+pub struct Lfu {
+    /// `(uses, last use)` of every position.
+    counts: Vec<(u64, u64)>,
+    /// `(uses, last use, position)`, the first is the victim.
+    order: BTreeSet<(u64, u64, usize)>,
+    clock: u64,
+}
+
+impl Lfu {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+impl EvictionPolicy for Lfu {
+    fn insert(&mut self, idx: usize) {
+        debug_assert_eq!(idx, self.counts.len());
+        let now = self.tick();
+        self.counts.push((1, now));
+        self.order.insert((1, now, idx));
+    }
+
+    fn access(&mut self, idx: usize) {
+        let now = self.tick();
+        let (uses, last) = self.counts[idx];
+        self.order.remove(&(uses, last, idx));
+        self.counts[idx] = (uses + 1, now);
+        self.order.insert((uses + 1, now, idx));
+    }
+
+    fn swap_remove(&mut self, idx: usize) {
+        let (uses, last) = self.counts.swap_remove(idx);
+        self.order.remove(&(uses, last, idx));
+        if let Some(&(uses, last)) = self.counts.get(idx) {
+            self.order.remove(&(uses, last, self.counts.len()));
+            self.order.insert((uses, last, idx));
+        }
+    }
+
+    fn victim(&self) -> Option<usize> {
+        self.order.first().map(|&(_, _, idx)| idx)
+    }
+
+    fn clear(&mut self) {
+        self.counts.clear();
+        self.order.clear();
+    }
+}
+
+fn unit_weight<K, V>(_: &K, _: &V) -> usize {
+    1
+}
+
+/// A map that holds entries up to a maximum total weight, and evicts entries chosen by the policy
+/// `P` to make room for new ones. By default every entry weighs 1, so the maximum is a number of
+/// entries. `with_weigher` sets a function to weigh entries by, e.g. their size in bytes. An
+/// entry is weighed once, when it's put in.
+#[derive(Clone)]
+pub struct BoundedCache<K, V, P = Lru, S = DefaultHashBuilder> {
+    /// Values with their weights.
+    entries: IndexMap<K, (V, usize), S>,
+    policy: P,
+    weigher: fn(&K, &V) -> usize,
+    weight: usize,
+    max_weight: usize,
+}
+
+impl<K, V, P, S> Debug for BoundedCache<K, V, P, S>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.entries.iter().map(|(k, (v, _))| (k, v)))
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, P: Default> BoundedCache<K, V, P, DefaultHashBuilder> {
+    /// Holds up to `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, DefaultHashBuilder::default())
+    }
+
+    /// Holds entries up to a total weight of `max_weight`.
+    pub fn with_weigher(max_weight: usize, weigher: fn(&K, &V) -> usize) -> Self {
+        Self::with_weigher_and_hasher(max_weight, weigher, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, P: Default, S> BoundedCache<K, V, P, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_weigher_and_hasher(capacity, unit_weight, hash_builder)
+    }
+
+    pub fn with_weigher_and_hasher(
+        max_weight: usize,
+        weigher: fn(&K, &V) -> usize,
+        hash_builder: S,
+    ) -> Self {
+        Self {
+            entries: IndexMap::with_hasher(hash_builder),
+            policy: P::default(),
+            weigher,
+            weight: 0,
+            max_weight,
+        }
+    }
+}
+
+impl<K, V, P, S> BoundedCache<K, V, P, S> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Total weight of the entries, the number of entries without a weigher.
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    pub fn max_weight(&self) -> usize {
+        self.max_weight
+    }
+
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Doesn't count as use. The order isn't the policy's, and changes as entries are removed,
+    /// so don't rely on it.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(k, (v, _))| (k, v))
+    }
+}
+
+impl<K, V, P, S> BoundedCache<K, V, P, S>
+where
+    P: EvictionPolicy,
+{
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.policy.clear();
+        self.weight = 0;
+    }
+
+    fn remove_index(&mut self, idx: usize) -> (K, V) {
+        let (k, (v, weight)) = self.entries.swap_remove_index(idx).unwrap();
+        self.policy.swap_remove(idx);
+        self.weight -= weight;
+        (k, v)
+    }
+
+    /// Evicts entries until `additional` more weight fits.
+    /// `additional` is at most the maximum weight.
+    fn make_room(&mut self, additional: usize, evicted: &mut Vec<(K, V)>) {
+        // Not `weight + additional`, which can overflow.
+        while self.weight > self.max_weight - additional {
+            let idx = self.policy.victim().expect("the cache is empty");
+            evicted.push(self.remove_index(idx));
+        }
+    }
+
+    /// Changes the maximum weight, returns the entries evicted to get under it.
+    pub fn set_max_weight(&mut self, max_weight: usize) -> Vec<(K, V)> {
+        self.max_weight = max_weight;
+        let mut evicted = Vec::new();
+        self.make_room(0, &mut evicted);
+        evicted
+    }
+}
+
+impl<K, V, P, S> BoundedCache<K, V, P, S>
+where
+    K: Hash + Eq,
+    P: EvictionPolicy,
+    S: BuildHasher,
+{
+    /// Counts as a use of the entry.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    /// Counts as a use of the entry. The entry isn't weighed again.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.entries.get_index_of(key)?;
+        self.policy.access(idx);
+        self.entries.get_index_mut(idx).map(|(_, (v, _))| v)
+    }
+
+    /// Doesn't count as a use of the entry.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(v, _)| v)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Puts in an entry and returns the entries evicted to make room for it, at most one without
+    /// a weigher. An entry with the same key is replaced and returned first, the new one starts
+    /// over as new to the policy.
+    ///
+    /// An entry heavier than the maximum weight is returned right away, without evicting others
+    /// or replacing the entry with the same key.
+    pub fn put(&mut self, key: K, value: V) -> Vec<(K, V)> {
+        let weight = (self.weigher)(&key, &value);
+        if weight > self.max_weight {
+            return vec![(key, value)];
+        }
+        let mut evicted = Vec::new();
+        if let Some(idx) = self.entries.get_index_of(&key) {
+            evicted.push(self.remove_index(idx));
+        }
+        self.make_room(weight, &mut evicted);
+        let (idx, _) = self.entries.insert_full(key, (value, weight));
+        self.policy.insert(idx);
+        self.weight += weight;
+        evicted
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = self.entries.get_index_of(key)?;
+        Some(self.remove_index(idx).1)
+    }
+}
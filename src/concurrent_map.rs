@@ -0,0 +1,388 @@
+//! A map for concurrent access from many threads.
+
+use core::{
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
+use std::{
+    boxed::Box,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    thread,
+    vec::Vec,
+};
+
+use crate::hash_map::{self, DefaultHashBuilder, HashMap};
+
+/// Splits the keys across a number of shards, each a `HashMap` behind its own `RwLock`, so that
+/// threads working on different shards don't contend. The shard of a key is chosen by the high
+/// bits of its hash, the shard's `HashMap` uses the low bits.
+///
+/// A panic while a shard is locked (e.g. in the closure passed to `compute`) doesn't poison the
+/// map, the shard stays usable.
+pub struct ConcurrentHashMap<K, V, S = DefaultHashBuilder> {
+    hash_builder: S,
+    shards: Box<[RwLock<HashMap<K, V, S>>]>,
+    /// `log2(shards.len())`.
+    shard_bits: u32,
+}
+
+impl<K, V, S> Debug for ConcurrentHashMap<K, V, S>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+        for shard in &*self.shards {
+            map.entries(read(shard).iter());
+        }
+        map.finish()
+    }
+}
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A few shards per thread, so that two threads rarely want the same one.
+fn default_shards() -> usize {
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    (threads * 4).next_power_of_two()
+}
+
+impl<K, V> ConcurrentHashMap<K, V, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+
+    /// `shards` is rounded up to a power of two.
+    pub fn with_shards(shards: usize) -> Self {
+        Self::with_shards_and_hasher(shards, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S>
+where
+    S: Clone,
+{
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_shards_and_hasher(default_shards(), hash_builder)
+    }
+
+    /// `shards` is rounded up to a power of two.
+    pub fn with_shards_and_hasher(shards: usize, hash_builder: S) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        Self {
+            shards: (0..shards)
+                .map(|_| RwLock::new(HashMap::with_hasher(hash_builder.clone())))
+                .collect::<Vec<_>>()
+                .into_boxed_slice(),
+            shard_bits: shards.trailing_zeros(),
+            hash_builder,
+        }
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S> {
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Sums up the lengths of the shards, which aren't locked all at once. With concurrent
+    /// inserts or removes, the result may not match any point in time.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| read(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| read(shard).is_empty())
+    }
+
+    pub fn clear(&self) {
+        for shard in &*self.shards {
+            write(shard).clear();
+        }
+    }
+
+    /// Weakly consistent: every shard is read-locked while its entries are being iterated, but
+    /// changes to other shards during the iteration may or may not be seen.
+    ///
+    /// # Deadlocks
+    ///
+    /// The iterator keeps the shard it's in read-locked until it moves on to the next one, and
+    /// every item keeps the lock of its shard until it's dropped. Calling a method that writes to
+    /// the map (`insert`, `remove`, `get_mut`, `compute`, ...) from the same thread while the
+    /// iterator or an item is alive can deadlock, as can `get` if another thread is waiting to
+    /// write.
+    pub fn iter(&self) -> Iter<'_, K, V, S> {
+        Iter {
+            shards: self.shards.iter(),
+            entries: hash_map::Iter::new(&[], &[]),
+            guard: None,
+        }
+    }
+}
+
+impl<K, V, S> Default for ConcurrentHashMap<K, V, S>
+where
+    S: Clone + Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> ConcurrentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn shard(&self, key: &K) -> &RwLock<HashMap<K, V, S>> {
+        let hash = self.hash_builder.hash_one(key);
+        let idx = hash.checked_shr(u64::BITS - self.shard_bits).unwrap_or(0);
+        &self.shards[idx as usize]
+    }
+
+    /// The entry stays read-locked until the `Ref` is dropped.
+    pub fn get(&self, key: &K) -> Option<Ref<'_, K, V, S>> {
+        let guard = read(self.shard(key));
+        let (k, v) = guard.get_kv(key)?;
+        let (key, value) = (NonNull::from(k), NonNull::from(v));
+        Some(Ref {
+            _guard: guard,
+            key,
+            value,
+        })
+    }
+
+    /// The entry stays write-locked until the `RefMut` is dropped.
+    pub fn get_mut(&self, key: &K) -> Option<RefMut<'_, K, V, S>> {
+        let mut guard = write(self.shard(key));
+        let (k, v) = guard.get_mut_kv(key)?;
+        let (key, value) = (NonNull::from(&*k), NonNull::from(v));
+        Some(RefMut {
+            _guard: guard,
+            key,
+            value,
+        })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        read(self.shard(key)).contains_key(key)
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        write(self.shard(&key)).insert(key, value)
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        write(self.shard(key)).remove(key)
+    }
+
+    /// Replaces the value of `key` with the result of `f`, atomically. `f` gets the old value, if
+    /// any, and the entry is removed if it returns `None`. If `f` panics, the entry is left as it
+    /// was.
+    pub fn compute<F>(&self, key: K, f: F)
+    where
+        F: FnOnce(&K, Option<&V>) -> Option<V>,
+    {
+        let mut guard = write(self.shard(&key));
+        match guard.get_mut(&key) {
+            Some(old) => match f(&key, Some(old)) {
+                Some(new) => *old = new,
+                None => {
+                    guard.remove(&key);
+                }
+            },
+            None => {
+                if let Some(new) = f(&key, None) {
+                    guard.insert(key, new);
+                }
+            }
+        }
+    }
+
+    /// Inserts `value` if `key` isn't in the map, otherwise calls `update` on the existing value.
+    pub fn upsert<F>(&self, key: K, value: V, update: F)
+    where
+        F: FnOnce(&mut V),
+    {
+        let mut guard = write(self.shard(&key));
+        match guard.get_mut(&key) {
+            Some(old) => update(old),
+            None => {
+                guard.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Read-locked entry of a `ConcurrentHashMap`.
+pub struct Ref<'a, K, V, S> {
+    _guard: RwLockReadGuard<'a, HashMap<K, V, S>>,
+    key: NonNull<K>,
+    value: NonNull<V>,
+}
+
+impl<K, V, S> Ref<'_, K, V, S> {
+    pub fn key(&self) -> &K {
+        // Safety: The entry is in the map behind `_guard`, which can't change while it's locked.
+        unsafe { self.key.as_ref() }
+    }
+
+    pub fn value(&self) -> &V {
+        // Safety: Same as `key`.
+        unsafe { self.value.as_ref() }
+    }
+
+    pub fn pair(&self) -> (&K, &V) {
+        (self.key(), self.value())
+    }
+}
+
+impl<K, V, S> Deref for Ref<'_, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
+
+impl<K: Debug, V: Debug, S> Debug for Ref<'_, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Ref")
+            .field(self.key())
+            .field(self.value())
+            .finish()
+    }
+}
+
+/// Write-locked entry of a `ConcurrentHashMap`.
+pub struct RefMut<'a, K, V, S> {
+    _guard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
+    key: NonNull<K>,
+    value: NonNull<V>,
+}
+
+impl<K, V, S> RefMut<'_, K, V, S> {
+    pub fn key(&self) -> &K {
+        // Safety: The entry is in the map behind `_guard`, which only changes through `self`.
+        unsafe { self.key.as_ref() }
+    }
+
+    pub fn value(&self) -> &V {
+        // Safety: Same as `key`.
+        unsafe { self.value.as_ref() }
+    }
+
+    pub fn value_mut(&mut self) -> &mut V {
+        // Safety: Same as `key`, and `&mut self` makes the borrow unique.
+        unsafe { self.value.as_mut() }
+    }
+}
+
+impl<K, V, S> Deref for RefMut<'_, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
+
+impl<K, V, S> DerefMut for RefMut<'_, K, V, S> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.value_mut()
+    }
+}
+
+impl<K: Debug, V: Debug, S> Debug for RefMut<'_, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RefMut")
+            .field(self.key())
+            .field(self.value())
+            .finish()
+    }
+}
+
+/// Item of `Iter`, read-locks its shard.
+pub struct IterRef<'a, K, V, S> {
+    _guard: SharedGuard<'a, K, V, S>,
+    key: NonNull<K>,
+    value: NonNull<V>,
+}
+
+impl<K, V, S> IterRef<'_, K, V, S> {
+    pub fn key(&self) -> &K {
+        // Safety: The entry is in the map behind `_guard`, which can't change while it's locked.
+        unsafe { self.key.as_ref() }
+    }
+
+    pub fn value(&self) -> &V {
+        // Safety: Same as `key`.
+        unsafe { self.value.as_ref() }
+    }
+
+    pub fn pair(&self) -> (&K, &V) {
+        (self.key(), self.value())
+    }
+}
+
+impl<K, V, S> Deref for IterRef<'_, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value()
+    }
+}
+
+impl<K: Debug, V: Debug, S> Debug for IterRef<'_, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("IterRef")
+            .field(self.key())
+            .field(self.value())
+            .finish()
+    }
+}
+
+type SharedGuard<'a, K, V, S> = Arc<RwLockReadGuard<'a, HashMap<K, V, S>>>;
+
+/// Iterator of `ConcurrentHashMap::iter`, see there for when it can deadlock.
+pub struct Iter<'a, K, V, S> {
+    shards: slice::Iter<'a, RwLock<HashMap<K, V, S>>>,
+    /// Iterator over the current shard, borrows from `guard`.
+    entries: hash_map::Iter<'a, K, V>,
+    guard: Option<SharedGuard<'a, K, V, S>>,
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S> {
+    type Item = IterRef<'a, K, V, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let (Some(guard), Some((k, v))) = (&self.guard, self.entries.next()) {
+                return Some(IterRef {
+                    _guard: Arc::clone(guard),
+                    key: NonNull::from(k),
+                    value: NonNull::from(v),
+                });
+            }
+            let guard = Arc::new(read(self.shards.next()?));
+            // Safety: The map is inside the `RwLock`, which lives for `'a`, and stays read-locked
+            // as long as `guard` or one of its clones in the yielded items is alive.
+            let map: &'a HashMap<K, V, S> = unsafe { &*(&**guard as *const HashMap<K, V, S>) };
+            self.entries = map.iter();
+            self.guard = Some(guard);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! A map whose entries expire after a time-to-live.
+
+use core::{
+    cell::Cell,
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    time::Duration,
+};
+use std::time::Instant;
+
+use crate::hash_map::{self, DefaultHashBuilder, HashMap};
+
+/// Don't purge before there are this many entries.
+const MIN_PURGE_LEN: usize = 16;
+
+/// Source of time for an `ExpiringHashMap`.
+pub trait Clock {
+    /// Time since some fixed point. Must never go backwards.
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// The monotonic clock of the OS, counting from when it was made.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to, for tests.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Timed<V> {
+    value: V,
+    /// When the entry expires, by the map's clock.
+    deadline: Duration,
+}
+
+/// A `HashMap` where every entry has a time-to-live. Expired entries are hidden right away, and
+/// removed on `purge_expired` or when `insert` finds the map has grown enough since the last
+/// purge. Until then they take up memory and are counted by `len`.
+#[derive(Clone)]
+pub struct ExpiringHashMap<K, V, C = SystemClock, S = DefaultHashBuilder> {
+    map: HashMap<K, Timed<V>, S>,
+    clock: C,
+    /// `insert` purges once the map has this many entries.
+    purge_len: usize,
+}
+
+impl<K, V, C, S> Debug for ExpiringHashMap<K, V, C, S>
+where
+    K: Debug,
+    V: Debug,
+    C: Clock,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> ExpiringHashMap<K, V, SystemClock, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock::default())
+    }
+}
+
+impl<K, V> Default for ExpiringHashMap<K, V, SystemClock, DefaultHashBuilder> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, C> ExpiringHashMap<K, V, C, DefaultHashBuilder> {
+    pub fn with_clock(clock: C) -> Self {
+        Self::with_clock_and_hasher(clock, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, C, S> ExpiringHashMap<K, V, C, S> {
+    pub fn with_clock_and_hasher(clock: C, hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hash_builder),
+            clock,
+            purge_len: MIN_PURGE_LEN,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Includes expired entries that weren't purged yet.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl<K, V, C: Clock, S> ExpiringHashMap<K, V, C, S> {
+    /// Skips expired entries.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.map.iter(),
+            now: self.clock.now(),
+        }
+    }
+}
+
+impl<K, V, C, S> ExpiringHashMap<K, V, C, S>
+where
+    K: Hash + Eq,
+    C: Clock,
+    S: BuildHasher,
+{
+    fn get_timed(&self, key: &K) -> Option<&Timed<V>> {
+        let now = self.clock.now();
+        self.map.get(key).filter(|timed| timed.deadline > now)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_timed(key).map(|timed| &timed.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let now = self.clock.now();
+        self.map
+            .get_mut(key)
+            .filter(|timed| timed.deadline > now)
+            .map(|timed| &mut timed.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_timed(key).is_some()
+    }
+
+    /// Time until the entry expires, `None` if it's not there or expired.
+    pub fn ttl(&self, key: &K) -> Option<Duration> {
+        let now = self.clock.now();
+        self.get_timed(key).map(|timed| timed.deadline - now)
+    }
+
+    /// Inserts an entry that expires after `ttl`, returns the old value unless it had expired.
+    pub fn insert(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        if self.map.len() >= self.purge_len {
+            self.purge_expired();
+            self.purge_len = usize::max(self.map.len() * 2, MIN_PURGE_LEN);
+        }
+        let now = self.clock.now();
+        let deadline = now.saturating_add(ttl);
+        let old = self.map.insert(key, Timed { value, deadline })?;
+        (old.deadline > now).then_some(old.value)
+    }
+
+    /// Returns the value unless it had expired. An expired entry is removed all the same.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let now = self.clock.now();
+        let old = self.map.remove(key)?;
+        (old.deadline > now).then_some(old.value)
+    }
+
+    /// Removes the expired entries, returns how many.
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.clock.now();
+        let len = self.map.len();
+        self.map.retain(|_, timed| timed.deadline > now);
+        len - self.map.len()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    inner: hash_map::Iter<'a, K, Timed<V>>,
+    now: Duration,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .by_ref()
+            .find(|(_, timed)| timed.deadline > self.now)
+            .map(|(k, timed)| (k, &timed.value))
+    }
+}
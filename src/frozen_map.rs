@@ -0,0 +1,181 @@
+//! An append-only map that can be inserted into through a shared reference.
+
+use alloc::boxed::Box;
+use core::{
+    cell::{Cell, UnsafeCell},
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    ptr::NonNull,
+};
+
+use crate::hash_map::{DefaultHashBuilder, HashMap};
+
+/// A `HashMap` whose entries can't be changed or removed through `&self`, only added. Every value
+/// is allocated on its own and the map only holds pointers to them, so growing the map doesn't
+/// move the values, and references to them stay valid for as long as the map is borrowed. Like the
+/// `elsa` crate's `FrozenMap`.
+///
+/// Hashing or comparing a key must not use the map, that panics.
+pub struct FrozenHashMap<K, V, S = DefaultHashBuilder> {
+    /// The values are from `Box::into_raw`, freed by `remove` or on drop.
+    map: UnsafeCell<HashMap<K, NonNull<V>, S>>,
+    /// Set while the map is borrowed inside a method, to catch reentrant calls.
+    in_use: Cell<bool>,
+    _marker: PhantomData<Box<V>>,
+}
+
+// Safety: The map owns the values like a `HashMap<K, Box<V>, S>` would.
+unsafe impl<K: Send, V: Send, S: Send> Send for FrozenHashMap<K, V, S> {}
+
+impl<K: Debug, V: Debug, S> Debug for FrozenHashMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_map(|map| {
+            // Safety: The values are alive until `self` is borrowed mutably.
+            let entries = map.iter().map(|(k, v)| (k, unsafe { v.as_ref() }));
+            f.debug_map().entries(entries).finish()
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> FrozenHashMap<K, V, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::from_map(HashMap::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_map(HashMap::with_capacity(capacity))
+    }
+}
+
+/// Clears `FrozenHashMap::in_use` when dropped, also if the map's user panics.
+struct InUse<'a>(&'a Cell<bool>);
+
+impl Drop for InUse<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+impl<K, V, S> FrozenHashMap<K, V, S> {
+    fn from_map(map: HashMap<K, NonNull<V>, S>) -> Self {
+        Self {
+            map: UnsafeCell::new(map),
+            in_use: Cell::new(false),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::from_map(HashMap::with_hasher(hash_builder))
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::from_map(HashMap::with_capacity_and_hasher(capacity, hash_builder))
+    }
+
+    /// Runs `f` on the map, panics if the map is already in use further up the stack.
+    fn with_map<R>(&self, f: impl FnOnce(&mut HashMap<K, NonNull<V>, S>) -> R) -> R {
+        assert!(!self.in_use.replace(true), "FrozenHashMap used reentrantly");
+        let _in_use = InUse(&self.in_use);
+        // Safety: `in_use` makes this the only reference to the map, the type isn't `Sync`, and
+        // `f` can't leak it.
+        f(unsafe { &mut *self.map.get() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.with_map(|map| map.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, S> Drop for FrozenHashMap<K, V, S> {
+    fn drop(&mut self) {
+        for (_, &value) in self.map.get_mut().iter() {
+            // Safety: Each value is owned by the map, and freed once.
+            drop(unsafe { Box::from_raw(value.as_ptr()) });
+        }
+    }
+}
+
+impl<K, V, S> Default for FrozenHashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::from_map(HashMap::default())
+    }
+}
+
+impl<K, V, S> FrozenHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let value = self.with_map(|map| map.get(key).copied())?;
+        // Safety: The value is only freed by `remove` or on drop, which need `&mut self`.
+        Some(unsafe { value.as_ref() })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.with_map(|map| map.contains_key(key))
+    }
+
+    /// Inserts the value unless the key is already there, returns the value in the map either
+    /// way.
+    pub fn insert(&self, key: K, value: V) -> &V {
+        self.insert_with(key, || value)
+    }
+
+    /// Like `insert`, but only makes the value if the key isn't there yet. `f` may use the map.
+    pub fn insert_with(&self, key: K, f: impl FnOnce() -> V) -> &V {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = Box::new(f());
+        let (value, unused) = self.with_map(|map| match map.get(&key) {
+            // `f` may have inserted the key itself.
+            Some(&old) => (old, Some(value)),
+            None => {
+                let value = NonNull::from(Box::leak(value));
+                map.insert(key, value);
+                (value, None)
+            }
+        });
+        // Dropped outside of `with_map`, its `Drop` may use the map.
+        drop(unused);
+        // Safety: As in `get`.
+        unsafe { value.as_ref() }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let value = self.map.get_mut().get(key)?;
+        // Safety: `&mut self` makes the borrow unique.
+        Some(unsafe { &mut *value.as_ptr() })
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.map.get_mut().remove(key)?;
+        // Safety: The value was owned by the map, and no references to it are left.
+        Some(*unsafe { Box::from_raw(value.as_ptr()) })
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for FrozenHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let map = Self::default();
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
@@ -0,0 +1,288 @@
+//! A multiset, counting how many times every element was added.
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+};
+
+use crate::hash_map::{self, DefaultHashBuilder, HashMap};
+
+/// A `HashMap` from elements to their counts, like Python's `Counter`. Counts are never zero, an
+/// element whose count drops to zero is removed.
+#[derive(Clone)]
+pub struct HashBag<T, S = DefaultHashBuilder> {
+    map: HashMap<T, usize, S>,
+    /// Sum of the counts.
+    len: usize,
+}
+
+impl<T: Debug, S> Debug for HashBag<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> HashBag<T, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+
+    /// `capacity` is in distinct elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl<T, S> HashBag<T, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hash_builder),
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_capacity_and_hasher(capacity, hash_builder),
+            len: 0,
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Number of elements, counting every copy.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of distinct elements.
+    pub fn distinct_len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.len = 0;
+    }
+
+    /// Every distinct element with its count.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            inner: self.map.iter(),
+        }
+    }
+
+    /// The `n` elements with the highest counts, highest first. Ties are in no particular order.
+    pub fn most_common(&self, n: usize) -> Vec<(&T, usize)> {
+        let mut counts: Vec<_> = self.iter().collect();
+        let by_count = |a: &(&T, usize), b: &(&T, usize)| b.1.cmp(&a.1);
+        if n < counts.len() {
+            counts.select_nth_unstable_by(n, by_count);
+            counts.truncate(n);
+        }
+        counts.sort_unstable_by(by_count);
+        counts
+    }
+}
+
+impl<T, S> Default for HashBag<T, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<T, S> HashBag<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns the new count.
+    pub fn add(&mut self, x: T) -> usize {
+        self.add_n(x, 1)
+    }
+
+    /// Adds `n` copies, returns the new count.
+    pub fn add_n(&mut self, x: T, n: usize) -> usize {
+        self.len += n;
+        match self.map.get_mut(&x) {
+            Some(count) => {
+                *count += n;
+                *count
+            }
+            None if n == 0 => 0,
+            None => {
+                self.map.insert(x, n);
+                n
+            }
+        }
+    }
+
+    /// Removes one copy, returns the new count, `None` if there was none.
+    pub fn remove_one(&mut self, x: &T) -> Option<usize> {
+        self.remove_n(x, 1)
+    }
+
+    /// Removes up to `n` copies, returns the new count, `None` if there were none.
+    pub fn remove_n(&mut self, x: &T, n: usize) -> Option<usize> {
+        let count = self.map.get_mut(x)?;
+        let removed = usize::min(*count, n);
+        *count -= removed;
+        self.len -= removed;
+        let count = *count;
+        if count == 0 {
+            self.map.remove(x);
+        }
+        Some(count)
+    }
+
+    /// Removes every copy, returns how many there were.
+    pub fn remove_all(&mut self, x: &T) -> usize {
+        let count = self.map.remove(x).unwrap_or(0);
+        self.len -= count;
+        count
+    }
+
+    /// How many copies there are, zero if none.
+    pub fn count(&self, x: &T) -> usize {
+        self.map.get(x).copied().unwrap_or(0)
+    }
+
+    pub fn contains(&self, x: &T) -> bool {
+        self.map.contains_key(x)
+    }
+
+    /// A bag with the counts of both, added.
+    pub fn sum(&self, other: &Self) -> Self
+    where
+        T: Clone,
+        S: Clone,
+    {
+        let mut sum = self.clone();
+        for (x, n) in other.iter() {
+            sum.add_n(x.clone(), n);
+        }
+        sum
+    }
+
+    /// A bag with the higher of the two counts of every element.
+    pub fn union(&self, other: &Self) -> Self
+    where
+        T: Clone,
+        S: Clone,
+    {
+        let mut union = self.clone();
+        for (x, n) in other.iter() {
+            let more = n.saturating_sub(self.count(x));
+            union.add_n(x.clone(), more);
+        }
+        union
+    }
+
+    /// A bag with the lower of the two counts of every element.
+    pub fn intersection(&self, other: &Self) -> Self
+    where
+        T: Clone,
+        S: Clone,
+    {
+        let mut intersection = Self::with_hasher(self.hasher().clone());
+        for (x, n) in self.iter() {
+            intersection.add_n(x.clone(), usize::min(n, other.count(x)));
+        }
+        intersection
+    }
+
+    /// A bag with the counts of `other` taken from those of `self`, down to zero.
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        T: Clone,
+        S: Clone,
+    {
+        let mut difference = Self::with_hasher(self.hasher().clone());
+        for (x, n) in self.iter() {
+            difference.add_n(x.clone(), n.saturating_sub(other.count(x)));
+        }
+        difference
+    }
+}
+
+impl<T, S> PartialEq for HashBag<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self.distinct_len() == other.distinct_len()
+            && self.iter().all(|(x, n)| other.count(x) == n)
+    }
+}
+
+impl<T, S> Eq for HashBag<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher,
+{
+}
+
+impl<T, S> FromIterator<T> for HashBag<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut bag = Self::default();
+        bag.extend(iter);
+        bag
+    }
+}
+
+impl<T, S> Extend<T> for HashBag<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.add(x);
+        }
+    }
+}
+
+impl<'a, T, S> IntoIterator for &'a HashBag<T, S> {
+    type Item = (&'a T, usize);
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the distinct elements of a `HashBag` with their counts.
+pub struct Iter<'a, T> {
+    inner: hash_map::Iter<'a, T, usize>,
+}
+
+impl<T> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (&'a T, usize);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(x, n)| (x, *n))
+    }
+}
@@ -1,13 +1,24 @@
-use std::{
-    collections::hash_map::DefaultHasher,
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    vec::{self, Vec},
+};
+use core::{
+    array,
+    cmp::Ordering,
     fmt::{self, Debug},
-    hash::{Hash, Hasher},
+    hash::{BuildHasher, Hash, Hasher},
+    marker::PhantomData,
     mem::{self, size_of},
-    option, slice, vec,
+    slice,
 };
 
 const LOAD_FACTOR_MAX: f64 = 0.75;
 const INIT_CAPACITY: usize = 32;
+/// Colliding entries in a bucket are kept sorted past this many, if the map has
+/// `HashMap::enable_treeify`.
+const TREEIFY_THRESHOLD: usize = 8;
+/// Sorted colliding entries turn back into a chain at this many.
+const UNTREEIFY_THRESHOLD: usize = 6;
 
 trait IsZst {
     const IS_ZST: bool;
@@ -17,16 +28,82 @@ impl<T> IsZst for T {
     const IS_ZST: bool = size_of::<Self>() == 0;
 }
 
+/// The hasher used by `HashMap` if none is specified.
+#[cfg(feature = "std")]
+pub type DefaultHashBuilder =
+    core::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+/// Without the `std` feature there is no default hasher, a `BuildHasher` (e.g. from
+/// `crate::hashers`) has to be specified with `HashMap::with_hasher`.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy)]
+pub enum DefaultHashBuilder {}
+
+/// `A` is the allocator for the buckets and the colliding entries, see `HashMap::with_hasher_in`.
 #[derive(Clone)]
-pub struct HashMap<K, V> {
-    buckets: Vec<Bucket<K, V>>,
+pub struct HashMap<K, V, S = DefaultHashBuilder, A: Allocator = Global> {
+    hash_builder: S,
+    buckets: Vec<Bucket<K, V, A>, A>,
+    arena: Arena<K, V, Vec<Slot<K, V>, A>>,
     len: usize,
+    /// Key comparator, if long collision chains should be kept sorted.
+    treeify: Option<KeyCmp<K>>,
+    /// Mixed into the hash of every key, regenerated when a pathologically long chain is found.
+    seed: u64,
+    reseed: Reseed,
+    /// Bumped whenever the map is rehashed, which moves every entry, so that older `Handle`s can
+    /// be told apart.
+    generation: u64,
+}
+
+/// Where an entry of a `HashMap` is, see `HashMap::insert_with_handle`. Removing the entry makes
+/// its handle stale, and so does rehashing (e.g. when the map grows), which moves every entry.
+/// Removing an entry may also move one other entry of its bucket, making that one's handle stale.
+/// Getting by a stale handle returns `None`. Look the key up again for a fresh one.
+///
+/// A handle is only meaningful to the map that made it, or clones made after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    bucket: usize,
+    /// Slot in the arena, `None` for the bucket's `first` entry.
+    slot: Option<usize>,
+    /// Generation of the map.
+    generation: u64,
+    /// Generation of the slot, or of the bucket for `first`.
+    entry_generation: u32,
+}
+
+/// Defense against pathologically long chains.
+#[derive(Debug, Clone, Default)]
+struct Reseed {
+    /// Longest chain (including the bucket's `first` entry) since the last rehash.
+    longest_chain: usize,
+    threshold: Option<usize>,
+    count: usize,
+    callback: Option<fn(&ReseedEvent)>,
+    /// Number of buckets at the last reseed. Reseeding again with as many buckets is pointless,
+    /// the keys' `Hash` is probably not affected by the seed.
+    last_buckets: Option<usize>,
+}
+
+/// Passed to the callback set by `HashMap::set_on_reseed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReseedEvent {
+    /// Length of the chain that triggered reseeding.
+    pub chain_len: usize,
+    /// Length of the chain after rehashing.
+    pub longest_chain: usize,
+    /// Number of entries in the map.
+    pub len: usize,
+    /// Number of reseeds, including this one, over the lifetime of the map.
+    pub count: usize,
 }
 
-impl<K, V> Debug for HashMap<K, V>
+impl<K, V, S, A> Debug for HashMap<K, V, S, A>
 where
     K: Debug,
     V: Debug,
+    A: Allocator,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_map().entries(self).finish()
@@ -38,21 +115,54 @@ fn hash(mut hasher: impl Hasher, x: impl Hash) -> u64 {
     hasher.finish()
 }
 
+/// A random seed, different from `old`.
+#[cfg(feature = "std")]
+fn new_seed(old: u64, _entropy: u64) -> u64 {
+    use std::collections::hash_map::RandomState;
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(old);
+    hasher.finish()
+}
+
+/// Without `std` there is no source of randomness. Mixes the old seed with whatever entropy the
+/// caller has (e.g. an address), which is enough against collisions that aren't targeted at this
+/// scheme.
+#[cfg(not(feature = "std"))]
+fn new_seed(old: u64, entropy: u64) -> u64 {
+    // SplitMix64.
+    let mut z = (old ^ entropy).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Hashes the key with the seed mixed in, mod the hash by the number of buckets. A zero seed
+/// isn't mixed in, so a map that never reseeded hashes its keys like the `BuildHasher` does.
+/// Returns `None` if there are no buckets.
+pub(crate) fn bucket_index<K: Hash>(
+    hash_builder: &impl BuildHasher,
+    seed: u64,
+    key: &K,
+    buckets: usize,
+) -> Option<usize> {
+    let mut hasher = hash_builder.build_hasher();
+    if seed != 0 {
+        hasher.write_u64(seed);
+    }
+    let hash = hash(hasher, key);
+    (hash as usize).checked_rem(buckets)
+}
+
 /// `Option` type with no niche value optimization and can be initialized as `None` by zeros in
 /// memory.
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum Option_<T> {
+    #[default]
     None = 0,
     Some(T),
 }
 
-impl<T> Default for Option_<T> {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
 #[allow(dead_code)]
 impl<T> Option_<T> {
     fn into_option(self) -> Option<T> {
@@ -84,178 +194,672 @@ impl<T> From<Option<T>> for Option_<T> {
     }
 }
 
+/// An entry in the overflow arena.
+///
+/// Occupied slots are linked into their bucket's collision chain through `next`. Vacant slots are
+/// linked into the arena's free list through the same field.
+#[derive(Debug, Clone)]
+pub(crate) struct Slot<K, V> {
+    entry: Option_<(K, V)>,
+    next: Option<usize>,
+    /// Bumped when the slot is vacated, for `Handle`s. Wraps around.
+    generation: u32,
+}
+
+/// Storage for colliding entries, shared by all buckets of a `HashMap`.
+///
+/// Colliding entries used to live in a `Vec` per bucket. Keeping them in one arena means a
+/// collision costs no separate allocation, and removed slots are reused through a free list.
+#[derive(Debug, Clone)]
+pub(crate) struct Arena<K, V, C = Vec<Slot<K, V>>> {
+    slots: C,
+    /// Head of the free list.
+    free: Option<usize>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> Slot<K, V> {
+    pub(crate) fn into_entry(self) -> Option<(K, V)> {
+        self.entry.into_option()
+    }
+
+    pub(crate) fn entry(&self) -> Option<(&K, &V)> {
+        let (k, v) = self.entry.as_option()?;
+        Some((k, v))
+    }
+
+    pub(crate) fn entry_mut(&mut self) -> Option<(&mut K, &mut V)> {
+        let (k, v) = self.entry.as_option_mut()?;
+        Some((k, v))
+    }
+
+    /// Leaves the slot vacant without putting it onto the free list.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn take_entry(&mut self) -> Option<(K, V)> {
+        mem::take(&mut self.entry).into_option()
+    }
+}
+
+/// Backing storage of an `Arena`.
+pub(crate) trait SlotStorage<K, V>: AsRef<[Slot<K, V>]> + AsMut<[Slot<K, V>]> {
+    /// Called when the free list is empty.
+    fn push(&mut self, slot: Slot<K, V>);
+}
+
+impl<K, V, A: Allocator> SlotStorage<K, V> for Vec<Slot<K, V>, A> {
+    fn push(&mut self, slot: Slot<K, V>) {
+        Vec::push(self, slot);
+    }
+}
+
+/// Fixed-capacity storage, for maps that never hold more entries than there are slots.
+impl<K, V, const N: usize> SlotStorage<K, V> for [Slot<K, V>; N] {
+    fn push(&mut self, _: Slot<K, V>) {
+        unreachable!("fixed-capacity arena is full")
+    }
+}
+
+impl<K, V, A: Allocator> Arena<K, V, Vec<Slot<K, V>, A>> {
+    fn new_in(alloc: A) -> Self {
+        Self {
+            slots: Vec::new_in(alloc),
+            free: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Vacates every slot, keeping the slots and their generations.
+    fn clear(&mut self) {
+        let mut free = None;
+        for (idx, slot) in self.slots.iter_mut().enumerate().rev() {
+            slot.entry = Option_::None;
+            slot.next = free;
+            slot.generation = slot.generation.wrapping_add(1);
+            free = Some(idx);
+        }
+        self.free = free;
+    }
+}
+
+impl<K, V, const N: usize> Arena<K, V, [Slot<K, V>; N]> {
+    /// An arena with all `N` slots on the free list.
+    pub(crate) fn vacant() -> Self {
+        Self {
+            slots: array::from_fn(|i| Slot {
+                entry: Option_::None,
+                next: (i + 1 < N).then_some(i + 1),
+                generation: 0,
+            }),
+            free: (N != 0).then_some(0),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V, C> Arena<K, V, C>
+where
+    C: SlotStorage<K, V>,
+{
+    pub(crate) fn slots(&self) -> &[Slot<K, V>] {
+        self.slots.as_ref()
+    }
+
+    pub(crate) fn slots_mut(&mut self) -> &mut [Slot<K, V>] {
+        self.slots.as_mut()
+    }
+
+    pub(crate) fn into_slots(self) -> C {
+        self.slots
+    }
+
+    /// Puts an entry into a vacant slot, returns the index of the slot.
+    fn alloc(&mut self, kv: (K, V), next: Option<usize>) -> usize {
+        match self.free {
+            Some(idx) => {
+                let slot = &mut self.slots.as_mut()[idx];
+                self.free = slot.next;
+                slot.entry = Option_::Some(kv);
+                slot.next = next;
+                idx
+            }
+            None => {
+                self.slots.push(Slot {
+                    entry: Option_::Some(kv),
+                    next,
+                    generation: 0,
+                });
+                self.slots().len() - 1
+            }
+        }
+    }
+
+    /// Takes the entry out of a slot and puts the slot onto the free list.
+    /// Returns the entry and the slot's old `next` link.
+    fn dealloc(&mut self, idx: usize) -> ((K, V), Option<usize>) {
+        let slot = &mut self.slots.as_mut()[idx];
+        let kv = mem::take(&mut slot.entry).into_option().unwrap();
+        let next = mem::replace(&mut slot.next, self.free);
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free = Some(idx);
+        (kv, next)
+    }
+
+    /// # Panics
+    /// Panics if the slot is vacant.
+    fn kv(&self, idx: usize) -> &(K, V) {
+        self.slots()[idx].entry.as_option().unwrap()
+    }
+
+    /// # Panics
+    /// Panics if the slot is vacant.
+    fn kv_mut(&mut self, idx: usize) -> &mut (K, V) {
+        self.slots_mut()[idx].entry.as_option_mut().unwrap()
+    }
+}
+
+/// Comparator of keys, for keeping long collision chains sorted.
+type KeyCmp<K> = fn(&K, &K) -> Ordering;
+
+/// Colliding entries of a bucket, all of which live in the `Arena`.
+#[derive(Debug, Clone)]
+enum Overflow<K, A: Allocator> {
+    /// A linked list of slots.
+    Chain {
+        head: usize,
+        tail: usize,
+        len: usize,
+    },
+    /// Slots sorted by key, for buckets with more than `TREEIFY_THRESHOLD` colliding entries in a
+    /// map with `HashMap::enable_treeify`.
+    Sorted { idxs: Vec<usize, A>, cmp: KeyCmp<K> },
+}
+
 /// `Bucket`'s default value is made from all zeros in memory.
 #[derive(Debug, Clone)]
-struct Bucket<K, V> {
+pub(crate) struct Bucket<K, V, A: Allocator = Global> {
     first: Option_<(K, V)>,
-    others: Option_<Vec<(K, V)>>,
+    others: Option_<Overflow<K, A>>,
+    /// Bumped when `first` loses its entry, for `Handle`s. Wraps around.
+    generation: u32,
 }
 
-impl<K, V> Default for Bucket<K, V> {
+impl<K, V, A: Allocator> Default for Bucket<K, V, A> {
     fn default() -> Self {
         Self {
             first: Option_::None,
             others: Option_::None,
+            generation: 0,
         }
     }
 }
 
-impl<K, V> Bucket<K, V> {
-    fn vec_of_empties(count: usize) -> Vec<Self> {
-        let mut vec = Vec::with_capacity(count);
-        if count != 0 && (!K::IS_ZST && !V::IS_ZST) {
-            // FIXME: Maybe UB?
-            unsafe { std::ptr::write_bytes(vec.as_mut_ptr(), 0, count) };
+impl<K, V, A: Allocator> Bucket<K, V, A> {
+    /// Takes the entry in `first`, the colliding entries are in the `Arena`.
+    pub(crate) fn into_first(self) -> Option<(K, V)> {
+        self.first.into_option()
+    }
+
+    pub(crate) fn first(&self) -> Option<(&K, &V)> {
+        let (k, v) = self.first.as_option()?;
+        Some((k, v))
+    }
+
+    pub(crate) fn first_mut(&mut self) -> Option<(&mut K, &mut V)> {
+        let (k, v) = self.first.as_option_mut()?;
+        Some((k, v))
+    }
+
+    /// Takes the entry in `first`, leaving the colliding entries alone.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn take_first(&mut self) -> Option<(K, V)> {
+        mem::take(&mut self.first).into_option()
+    }
+
+    /// Number of entries, including `first`.
+    fn len(&self) -> usize {
+        let others = match self.others.as_option() {
+            None => 0,
+            Some(Overflow::Chain { len, .. }) => *len,
+            Some(Overflow::Sorted { idxs, .. }) => idxs.len(),
+        };
+        match self.first {
+            Option_::Some(_) => others + 1,
+            Option_::None => others,
+        }
+    }
+
+    /// Removes all entries, the colliding ones are left in the `Arena`.
+    fn clear(&mut self) {
+        self.first = Option_::None;
+        self.others = Option_::None;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn vec_of_empties_in(count: usize, alloc: A) -> Vec<Self, A> {
+        let mut vec = Vec::with_capacity_in(count, alloc);
+        if count != 0 {
+            // Safety: `Option_::None` is all zeros, and `Bucket` is never a ZST.
+            unsafe { core::ptr::write_bytes(vec.as_mut_ptr(), 0, count) };
         }
         unsafe { vec.set_len(count) };
         vec
     }
 
-    /// FIXME: Maybe make this into an iterator in the future.
-    fn for_each_kv(self, mut f: impl FnMut(K, V)) {
-        if let Option_::Some((k, v)) = self.first {
-            f(k, v)
+    /// Converts a chain longer than `TREEIFY_THRESHOLD` into sorted slots, allocated in `alloc`.
+    fn treeify_if_needed<C: SlotStorage<K, V>>(
+        &mut self,
+        arena: &mut Arena<K, V, C>,
+        cmp: KeyCmp<K>,
+        alloc: A,
+    ) {
+        let Option_::Some(Overflow::Chain { head, len, .. }) = self.others else {
+            return;
+        };
+        if len <= TREEIFY_THRESHOLD {
+            return;
         }
-        if let Some(others) = self.others.into_option() {
-            for (k, v) in others {
-                f(k, v);
-            }
+        let mut idxs = Vec::with_capacity_in(len, alloc);
+        let mut next = Some(head);
+        while let Some(idx) = next {
+            idxs.push(idx);
+            next = arena.slots_mut()[idx].next.take();
         }
+        idxs.sort_unstable_by(|&lhs, &rhs| cmp(&arena.kv(lhs).0, &arena.kv(rhs).0));
+        self.others = Option_::Some(Overflow::Sorted { idxs, cmp });
     }
 
-    fn iter(&self) -> BucketIter<K, V> {
-        self.into_iter()
+    /// Converts sorted slots back into a chain once there are no more than `UNTREEIFY_THRESHOLD`
+    /// of them.
+    fn untreeify_if_needed<C: SlotStorage<K, V>>(&mut self, arena: &mut Arena<K, V, C>) {
+        let Option_::Some(Overflow::Sorted { idxs, .. }) = &self.others else {
+            return;
+        };
+        if idxs.len() > UNTREEIFY_THRESHOLD {
+            return;
+        }
+        let mut next = None;
+        for &idx in idxs.iter().rev() {
+            arena.slots_mut()[idx].next = next;
+            next = Some(idx);
+        }
+        self.others = match next {
+            Some(head) => Option_::Some(Overflow::Chain {
+                head,
+                tail: *idxs.last().unwrap(),
+                len: idxs.len(),
+            }),
+            None => Option_::None,
+        };
     }
+}
 
-    fn iter_mut(&mut self) -> BucketIterMut<K, V> {
-        self.into_iter()
+impl<K, V, A: Allocator> Bucket<K, V, A> {
+    /// Adds an entry without looking for an equal key, after the entries already there. For
+    /// rehashing, where keys are known to be distinct, and for `MultiMap`, where they may not be.
+    pub(crate) fn push<C: SlotStorage<K, V>>(
+        &mut self,
+        arena: &mut Arena<K, V, C>,
+        treeify: Option<(KeyCmp<K>, A)>,
+        k: K,
+        v: V,
+    ) {
+        if let Option_::None = self.first {
+            self.first = Option_::Some((k, v));
+            return;
+        }
+        match &mut self.others {
+            Option_::Some(Overflow::Sorted { idxs, cmp }) => {
+                let i = idxs.partition_point(|&idx| cmp(&arena.kv(idx).0, &k).is_le());
+                idxs.insert(i, arena.alloc((k, v), None));
+            }
+            Option_::Some(Overflow::Chain { tail, len, .. }) => {
+                let idx = arena.alloc((k, v), None);
+                arena.slots_mut()[*tail].next = Some(idx);
+                *tail = idx;
+                *len += 1;
+            }
+            Option_::None => {
+                let idx = arena.alloc((k, v), None);
+                self.others = Option_::Some(Overflow::Chain {
+                    head: idx,
+                    tail: idx,
+                    len: 1,
+                });
+            }
+        }
+        if let Some((cmp, alloc)) = treeify {
+            self.treeify_if_needed(arena, cmp, alloc);
+        }
+    }
+
+    /// The entries of the bucket, `first` then the colliding ones.
+    pub(crate) fn iter<'a>(&'a self, slots: &'a [Slot<K, V>]) -> BucketIter<'a, K, V> {
+        let (chain, sorted) = match self.others.as_option() {
+            None => (None, [].iter()),
+            Some(Overflow::Chain { head, .. }) => (Some(*head), [].iter()),
+            Some(Overflow::Sorted { idxs, .. }) => (None, idxs.iter()),
+        };
+        BucketIter {
+            first: self.first(),
+            chain,
+            sorted,
+            slots,
+        }
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a Bucket<K, V> {
-    type Item = (&'a K, &'a V);
-    type IntoIter = BucketIter<'a, K, V>;
-    fn into_iter(self) -> Self::IntoIter {
-        BucketIter::new(
-            self.first.as_option(),
-            self.others
-                .as_option()
-                .map(Vec::as_slice)
-                .unwrap_or_default(),
-        )
+/// Iterator over the entries of one bucket, see `Bucket::iter`.
+pub(crate) struct BucketIter<'a, K, V> {
+    first: Option<(&'a K, &'a V)>,
+    /// Next slot of a chain.
+    chain: Option<usize>,
+    sorted: slice::Iter<'a, usize>,
+    slots: &'a [Slot<K, V>],
+}
+
+impl<K, V> BucketIter<'_, K, V> {
+    pub(crate) fn empty() -> Self {
+        Self {
+            first: None,
+            chain: None,
+            sorted: [].iter(),
+            slots: &[],
+        }
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a mut Bucket<K, V> {
-    type Item = (&'a mut K, &'a mut V);
-    type IntoIter = BucketIterMut<'a, K, V>;
-    fn into_iter(self) -> Self::IntoIter {
-        BucketIterMut::new(
-            self.first.as_option_mut(),
-            self.others
-                .as_option_mut()
-                .map(Vec::as_mut_slice)
-                .unwrap_or_default(),
-        )
+impl<K, V> Clone for BucketIter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            first: self.first,
+            chain: self.chain,
+            sorted: self.sorted.clone(),
+            slots: self.slots,
+        }
     }
 }
 
-impl<K, V> IntoIterator for Bucket<K, V> {
-    type Item = (K, V);
-    type IntoIter = BucketIntoIter<K, V>;
-    fn into_iter(self) -> Self::IntoIter {
-        BucketIntoIter::new(
-            self.first.into_option(),
-            self.others.into_option().unwrap_or_default(),
-        )
+impl<'a, K, V> Iterator for BucketIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(kv) = self.first.take() {
+            return Some(kv);
+        }
+        let idx = match self.chain {
+            Some(idx) => {
+                self.chain = self.slots[idx].next;
+                idx
+            }
+            None => *self.sorted.next()?,
+        };
+        self.slots[idx].entry()
     }
 }
 
-impl<K, V> Bucket<K, V>
+impl<K, V, A> Bucket<K, V, A>
 where
     K: Eq,
+    A: Allocator,
 {
-    fn insert(&mut self, k: K, v: V) -> Option<(K, V)> {
+    /// Finds the index of the slot that holds key `k`.
+    fn find_in_overflow<C: SlotStorage<K, V>>(
+        &self,
+        arena: &Arena<K, V, C>,
+        k: &K,
+    ) -> Option<usize> {
+        match self.others.as_option()? {
+            Overflow::Chain { head, .. } => {
+                let mut next = Some(*head);
+                while let Some(idx) = next {
+                    if &arena.kv(idx).0 == k {
+                        return Some(idx);
+                    }
+                    next = arena.slots()[idx].next;
+                }
+                None
+            }
+            Overflow::Sorted { idxs, cmp } => idxs
+                .binary_search_by(|&idx| cmp(&arena.kv(idx).0, k))
+                .ok()
+                .map(|i| idxs[i]),
+        }
+    }
+
+    /// `treeify` is the key comparator if the map has `HashMap::enable_treeify`, and the
+    /// allocator of the map.
+    pub(crate) fn insert<C: SlotStorage<K, V>>(
+        &mut self,
+        arena: &mut Arena<K, V, C>,
+        treeify: Option<(KeyCmp<K>, A)>,
+        k: K,
+        v: V,
+    ) -> Option<(K, V)> {
+        self.insert_located(arena, treeify, k, v).1
+    }
+
+    /// Like `insert`, also returns where the entry ended up. `None` stands for `first`, `Some`
+    /// for a slot of the arena.
+    fn insert_located<C: SlotStorage<K, V>>(
+        &mut self,
+        arena: &mut Arena<K, V, C>,
+        treeify: Option<(KeyCmp<K>, A)>,
+        k: K,
+        v: V,
+    ) -> (Option<usize>, Option<(K, V)>) {
         match &mut self.first {
             first @ Option_::None => {
                 *first = Option_::Some((k, v));
-                None
+                return (None, None);
             }
             Option_::Some((ref k0, _)) if k0 == &k => {
-                mem::replace(&mut self.first, Option_::Some((k, v))).into_option()
+                let old = mem::replace(&mut self.first, Option_::Some((k, v)));
+                return (None, old.into_option());
             }
-            Option_::Some(_) => {
-                let others = match &mut self.others {
-                    Option_::Some(others) => others,
-                    others @ Option_::None => {
-                        *others = Option_::Some(Vec::with_capacity(1));
-                        // Safety: Was just set as Some.
-                        unsafe { others.as_option_mut().unwrap_unchecked() }
+            Option_::Some(_) => (),
+        }
+        match &mut self.others {
+            Option_::Some(Overflow::Sorted { idxs, cmp }) => {
+                match idxs.binary_search_by(|&idx| cmp(&arena.kv(idx).0, &k)) {
+                    Ok(i) => (
+                        Some(idxs[i]),
+                        Some(mem::replace(arena.kv_mut(idxs[i]), (k, v))),
+                    ),
+                    Err(i) => {
+                        let idx = arena.alloc((k, v), None);
+                        idxs.insert(i, idx);
+                        (Some(idx), None)
                     }
-                };
-                others.push((k, v));
-                None
+                }
+            }
+            _ => {
+                if let Some(idx) = self.find_in_overflow(arena, &k) {
+                    return (Some(idx), Some(mem::replace(arena.kv_mut(idx), (k, v))));
+                }
+                let idx;
+                self.others = Option_::Some(match self.others {
+                    Option_::Some(Overflow::Chain { head, tail, len }) => {
+                        idx = arena.alloc((k, v), Some(head));
+                        Overflow::Chain {
+                            head: idx,
+                            tail,
+                            len: len + 1,
+                        }
+                    }
+                    _ => {
+                        idx = arena.alloc((k, v), None);
+                        Overflow::Chain {
+                            head: idx,
+                            tail: idx,
+                            len: 1,
+                        }
+                    }
+                });
+                // Treeifying keeps the entries in their slots.
+                if let Some((cmp, alloc)) = treeify {
+                    self.treeify_if_needed(arena, cmp, alloc);
+                }
+                (Some(idx), None)
             }
         }
     }
 
-    fn get<'a>(&'a self, k: &K) -> Option<(&'a K, &'a V)> {
+    pub(crate) fn get<'a, C: SlotStorage<K, V>>(
+        &'a self,
+        arena: &'a Arena<K, V, C>,
+        k: &K,
+    ) -> Option<(&'a K, &'a V)> {
         match &self.first {
             Option_::Some((k0, v)) if k == k0 => Some((k0, v)),
-            _ => self
-                .others
-                .as_option()?
-                .iter()
-                .find(|(k0, _)| k0 == k)
-                .map(|(k, v)| (k, v)),
+            _ => {
+                let (k, v) = arena.kv(self.find_in_overflow(arena, k)?);
+                Some((k, v))
+            }
         }
     }
 
-    fn get_mut<'a>(&'a mut self, k: &K) -> Option<(&'a mut K, &'a mut V)> {
-        match &mut self.first {
-            Option_::Some((k0, v)) if k == k0 => Some((k0, v)),
-            _ => self
-                .others
-                .as_option_mut()?
-                .iter_mut()
-                .find(|(k0, _)| k0 == k)
-                .map(|(k, v)| (k, v)),
+    pub(crate) fn get_mut<'a, C: SlotStorage<K, V>>(
+        &'a mut self,
+        arena: &'a mut Arena<K, V, C>,
+        k: &K,
+    ) -> Option<(&'a mut K, &'a mut V)> {
+        if matches!(&self.first, Option_::Some((k0, _)) if k == k0) {
+            let (k, v) = self.first.as_option_mut()?;
+            return Some((k, v));
         }
+        let idx = self.find_in_overflow(arena, k)?;
+        let (k, v) = arena.kv_mut(idx);
+        Some((k, v))
     }
 
-    fn remove(&mut self, k: &K) -> Option<V> {
-        match &mut self.first {
-            Option_::Some((k0, _)) if k == k0 => {
-                let (_, v) = mem::replace(&mut self.first, Option_::None).into_option()?;
-                if let Option_::Some(vec) = &mut self.others {
-                    self.first = vec.pop().into();
-                    if vec.is_empty() {
-                        self.others = Option_::None;
+    pub(crate) fn remove<C: SlotStorage<K, V>>(
+        &mut self,
+        arena: &mut Arena<K, V, C>,
+        k: &K,
+    ) -> Option<(K, V)> {
+        if matches!(&self.first, Option_::Some((k0, _)) if k == k0) {
+            let kv = mem::take(&mut self.first).into_option();
+            self.generation = self.generation.wrapping_add(1);
+            // Move one of the colliding entries into `first`.
+            match &mut self.others {
+                Option_::None => (),
+                Option_::Some(Overflow::Chain { head, len, .. }) => {
+                    let (head_kv, next) = arena.dealloc(*head);
+                    self.first = Option_::Some(head_kv);
+                    match next {
+                        Some(next) => {
+                            *head = next;
+                            *len -= 1;
+                        }
+                        None => self.others = Option_::None,
+                    }
+                }
+                Option_::Some(Overflow::Sorted { idxs, .. }) => {
+                    let idx = idxs.pop().unwrap();
+                    self.first = Option_::Some(arena.dealloc(idx).0);
+                    self.untreeify_if_needed(arena);
+                }
+            }
+            return kv;
+        }
+        match &mut self.others {
+            Option_::None => None,
+            Option_::Some(Overflow::Chain { head, tail, len }) => {
+                let mut prev: Option<usize> = None;
+                let mut next = Some(*head);
+                while let Some(idx) = next {
+                    if &arena.kv(idx).0 == k {
+                        let (kv, next) = arena.dealloc(idx);
+                        *len -= 1;
+                        match (prev, next) {
+                            (Some(prev), None) => {
+                                arena.slots_mut()[prev].next = None;
+                                *tail = prev;
+                            }
+                            (Some(prev), _) => arena.slots_mut()[prev].next = next,
+                            (None, Some(next)) => *head = next,
+                            (None, None) => self.others = Option_::None,
+                        }
+                        return Some(kv);
                     }
+                    prev = Some(idx);
+                    next = arena.slots()[idx].next;
                 }
-                Some(v)
+                None
             }
-            _ => {
-                let others = self.others.as_option_mut()?;
-                let idx = others.iter().position(|(k0, _)| k == k0)?;
-                let (_, v) = others.remove(idx);
-                Some(v)
+            Option_::Some(Overflow::Sorted { idxs, cmp }) => {
+                let i = idxs
+                    .binary_search_by(|&idx| cmp(&arena.kv(idx).0, k))
+                    .ok()?;
+                let (kv, _) = arena.dealloc(idxs.remove(i));
+                self.untreeify_if_needed(arena);
+                Some(kv)
             }
         }
     }
 }
 
-impl<K, V> HashMap<K, V> {
+#[cfg(feature = "std")]
+impl<K, V> HashMap<K, V, DefaultHashBuilder> {
     pub fn new() -> Self {
         Self::with_capacity(0)
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, A: Allocator + Clone> HashMap<K, V, DefaultHashBuilder, A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_capacity_in(0, alloc)
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::with_capacity_and_hasher_in(capacity, DefaultHashBuilder::default(), alloc)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher_in(capacity, hash_builder, Global)
+    }
+}
+
+impl<K, V, S, A: Allocator + Clone> HashMap<K, V, S, A> {
+    /// The buckets and the colliding entries are allocated in `alloc`, the keys and values live
+    /// in them.
+    pub fn with_hasher_in(hash_builder: S, alloc: A) -> Self {
+        Self::with_capacity_and_hasher_in(0, hash_builder, alloc)
+    }
+
+    pub fn with_capacity_and_hasher_in(capacity: usize, hash_builder: S, alloc: A) -> Self {
         Self {
-            buckets: Bucket::vec_of_empties(capacity),
+            hash_builder,
+            buckets: Bucket::vec_of_empties_in(capacity, alloc.clone()),
+            arena: Arena::new_in(alloc),
             len: 0,
+            treeify: None,
+            seed: 0,
+            reseed: Reseed::default(),
+            generation: 0,
         }
     }
+}
+
+impl<K, V, S, A: Allocator> HashMap<K, V, S, A> {
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.buckets.allocator()
+    }
 
     pub fn len(&self) -> usize {
         self.len
@@ -273,86 +877,219 @@ impl<K, V> HashMap<K, V> {
         }
     }
 
-    pub fn iter(&self) -> Iter<K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V, A> {
         self.into_iter()
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V, A> {
         self.into_iter()
     }
 
+    /// Removes all entries, keeping the buckets.
+    pub fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Bucket::clear);
+        self.arena.clear();
+        self.len = 0;
+    }
+
+    fn handle_at(&self, bucket: usize, slot: Option<usize>) -> Handle {
+        let entry_generation = match slot {
+            None => self.buckets[bucket].generation,
+            Some(idx) => self.arena.slots[idx].generation,
+        };
+        Handle {
+            bucket,
+            slot,
+            generation: self.generation,
+            entry_generation,
+        }
+    }
+
+    /// Whether the entry of the handle is still where it was.
+    fn is_fresh(&self, handle: Handle) -> bool {
+        let entry_generation = match handle.slot {
+            None => self
+                .buckets
+                .get(handle.bucket)
+                .map(|bucket| bucket.generation),
+            Some(idx) => self.arena.slots.get(idx).map(|slot| slot.generation),
+        };
+        handle.generation == self.generation && entry_generation == Some(handle.entry_generation)
+    }
+
+    /// Gets the entry without hashing, `None` if the handle is stale.
+    pub fn get_kv_by_handle(&self, handle: Handle) -> Option<(&K, &V)> {
+        if !self.is_fresh(handle) {
+            return None;
+        }
+        match handle.slot {
+            None => self.buckets[handle.bucket].first(),
+            Some(idx) => self.arena.slots[idx].entry(),
+        }
+    }
+
+    pub fn get_by_handle(&self, handle: Handle) -> Option<&V> {
+        self.get_kv_by_handle(handle).map(|(_, v)| v)
+    }
+
+    pub fn get_mut_by_handle(&mut self, handle: Handle) -> Option<&mut V> {
+        if !self.is_fresh(handle) {
+            return None;
+        }
+        let (_, v) = match handle.slot {
+            None => self.buckets[handle.bucket].first_mut()?,
+            Some(idx) => self.arena.slots[idx].entry_mut()?,
+        };
+        Some(v)
+    }
+
+    /// The buckets and the arena slots, for iterating.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn parts(&self) -> (&[Bucket<K, V, A>], &[Slot<K, V>]) {
+        (&self.buckets, &self.arena.slots)
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn parts_mut(&mut self) -> (&mut [Bucket<K, V, A>], &mut [Slot<K, V>]) {
+        (&mut self.buckets, &mut self.arena.slots)
+    }
+
+    /// If `HashMap::enable_treeify` was called.
+    pub fn is_treeify_enabled(&self) -> bool {
+        self.treeify.is_some()
+    }
+
+    /// Chains longer than this trigger regenerating the hash seed and rehashing.
+    /// `None` disables reseeding, which is the default: a reseeded map hashes its keys
+    /// differently, which changes the iteration order.
+    pub fn reseed_threshold(&self) -> Option<usize> {
+        self.reseed.threshold
+    }
+
+    pub fn set_reseed_threshold(&mut self, threshold: Option<usize>) {
+        self.reseed.threshold = threshold;
+    }
+
+    /// Sets a callback that is called after every automatic reseed, e.g. for alerting.
+    pub fn set_on_reseed(&mut self, callback: Option<fn(&ReseedEvent)>) {
+        self.reseed.callback = callback;
+    }
+
+    /// Number of automatic reseeds over the lifetime of the map.
+    pub fn reseed_count(&self) -> usize {
+        self.reseed.count
+    }
+
+    /// Length of the longest chain since the last rehash.
+    pub fn longest_chain(&self) -> usize {
+        self.reseed.longest_chain
+    }
+
     /// If `K` and `V` are both ZSTs.
     const fn is_zst() -> bool {
         K::IS_ZST && V::IS_ZST
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a HashMap<K, V> {
+impl<'a, K, V, S, A: Allocator> IntoIterator for &'a HashMap<K, V, S, A> {
     type Item = (&'a K, &'a V);
 
-    type IntoIter = Iter<'a, K, V>;
+    type IntoIter = Iter<'a, K, V, A>;
 
     fn into_iter(self) -> Self::IntoIter {
-        Iter::new(&self.buckets)
+        let (buckets, slots) = self.parts();
+        Iter::new(buckets, slots)
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a mut HashMap<K, V> {
+impl<'a, K, V, S, A: Allocator> IntoIterator for &'a mut HashMap<K, V, S, A> {
     type Item = (&'a mut K, &'a mut V);
 
-    type IntoIter = IterMut<'a, K, V>;
+    type IntoIter = IterMut<'a, K, V, A>;
 
     fn into_iter(self) -> Self::IntoIter {
-        IterMut::new(&mut self.buckets)
+        let (buckets, slots) = self.parts_mut();
+        IterMut::new(buckets, slots)
     }
 }
 
-impl<K, V> IntoIterator for HashMap<K, V> {
+impl<K, V, S, A: Allocator> IntoIterator for HashMap<K, V, S, A> {
     type Item = (K, V);
 
-    type IntoIter = IntoIter<K, V>;
+    type IntoIter = IntoIter<K, V, A>;
 
     fn into_iter(self) -> Self::IntoIter {
-        IntoIter::new(self.buckets)
+        IntoIter::new(self.buckets, self.arena.slots)
     }
 }
 
-impl<K, V> Default for HashMap<K, V> {
+impl<K, V, S> Default for HashMap<K, V, S>
+where
+    S: Default,
+{
     fn default() -> Self {
-        Self::new()
+        Self::with_hasher(S::default())
     }
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V, S, A> HashMap<K, V, S, A>
 where
     K: Hash + Eq,
+    S: BuildHasher,
+    A: Allocator + Clone,
 {
     fn load_factor(&self) -> f64 {
         (self.len() as f64) / (self.capacity() as f64)
     }
 
+    /// Expands the map if inserting one more entry would exceed the max load factor.
     fn expand_if_needed(&mut self) {
         if self.buckets.is_empty() {
             self.resize(INIT_CAPACITY);
-        } else if self.load_factor() > LOAD_FACTOR_MAX {
+        } else if self.load_factor() >= LOAD_FACTOR_MAX {
             self.resize(self.capacity() * 4);
         }
     }
 
+    /// Like `expand_if_needed`, but entries for which `alive` returns false don't count toward
+    /// the load, and are dropped when the map is rehashed. Rehashes at the same capacity if at
+    /// least half of the entries are dead, so that it doesn't rehash again a few inserts later.
+    /// For `WeakValueHashMap`.
+    pub(crate) fn expand_purging_if_needed(&mut self, alive: impl Fn(&V) -> bool) {
+        if self.buckets.is_empty() || self.load_factor() < LOAD_FACTOR_MAX {
+            self.expand_if_needed();
+            return;
+        }
+        let live = self.iter().filter(|(_, v)| alive(v)).count();
+        let new_capacity = if live as f64 <= self.capacity() as f64 * LOAD_FACTOR_MAX / 2.0 {
+            self.buckets.len()
+        } else {
+            self.capacity() * 4
+        };
+        self.rebuild(new_capacity, |_, v| alive(v));
+    }
+
     /// This function is `pub(crate)` for use in testing.
     /// # Panics
     /// Panics if `new_capacity == 0` and `self.len() != 0`.
     pub(crate) fn resize(&mut self, new_capacity: usize) {
-        if Self::is_zst() {
-            self.buckets = Bucket::vec_of_empties(new_capacity);
-            return;
-        }
+        self.rebuild(new_capacity, |_, _| true);
+    }
+
+    /// Rehashes into `new_capacity` buckets, keeping only the entries for which `keep` returns
+    /// true.
+    pub(crate) fn rebuild(
+        &mut self,
+        new_capacity: usize,
+        mut keep: impl FnMut(&K, &mut V) -> bool,
+    ) {
         // FIXME: Realloc instead of rehashing into a new allocation?
-        let old_buckets: Vec<Bucket<K, V>> = {
-            let mut buckets = Bucket::vec_of_empties(new_capacity);
-            mem::swap(&mut self.buckets, &mut buckets);
-            buckets
-        };
+        let alloc = self.allocator().clone();
+        let old_buckets = mem::replace(
+            &mut self.buckets,
+            Bucket::vec_of_empties_in(new_capacity, alloc.clone()),
+        );
+        let old_arena = mem::replace(&mut self.arena, Arena::new_in(alloc.clone()));
         if cfg!(debug_assertions) && new_capacity == 0 {
             // Only do this assertion in debug mode, because it would panic anyways later during
             // rehashing.
@@ -361,36 +1098,107 @@ where
                 "`HashMap::resize` called with `new_capacity = 0`, but `self.len() > 0`"
             );
         }
-        for old_bucket in old_buckets {
-            old_bucket.for_each_kv(|k, v| {
-                self.bucket_mut(&k).unwrap().insert(k, v);
+        self.reseed.longest_chain = 0;
+        self.generation += 1;
+        // Bucket by bucket, so that entries with equal keys in a `MultiMap` keep their order.
+        let mut old_arena = old_arena;
+        for bucket in old_buckets {
+            let Bucket { first, others, .. } = bucket;
+            if let Option_::Some((k, v)) = first {
+                self.reinsert(k, v, &mut keep);
+            }
+            match others {
+                Option_::None => (),
+                Option_::Some(Overflow::Chain { head, .. }) => {
+                    let mut next = Some(head);
+                    while let Some(idx) = next {
+                        let ((k, v), after) = old_arena.dealloc(idx);
+                        self.reinsert(k, v, &mut keep);
+                        next = after;
+                    }
+                }
+                Option_::Some(Overflow::Sorted { idxs, .. }) => {
+                    for idx in idxs {
+                        let ((k, v), _) = old_arena.dealloc(idx);
+                        self.reinsert(k, v, &mut keep);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Puts an entry back while rebuilding, unless `keep` says otherwise.
+    fn reinsert(&mut self, k: K, mut v: V, keep: &mut impl FnMut(&K, &mut V) -> bool) {
+        if !keep(&k, &mut v) {
+            self.len -= 1;
+            return;
+        }
+        let idx = self.index(&k).unwrap();
+        let treeify = self.treeify.map(|cmp| (cmp, self.allocator().clone()));
+        let bucket = &mut self.buckets[idx];
+        bucket.push(&mut self.arena, treeify, k, v);
+        self.reseed.longest_chain = usize::max(self.reseed.longest_chain, bucket.len());
+    }
+
+    /// Regenerates the seed and rehashes if the chain of length `chain_len` is too long.
+    fn reseed_if_needed(&mut self, chain_len: usize) {
+        self.reseed.longest_chain = usize::max(self.reseed.longest_chain, chain_len);
+        if self.needs_reseed(chain_len) {
+            self.reseed_and_rehash(chain_len);
+        }
+    }
+
+    /// Whether a chain of length `chain_len` is too long, unless reseeding didn't help before.
+    fn needs_reseed(&self, chain_len: usize) -> bool {
+        self.reseed
+            .threshold
+            .is_some_and(|threshold| chain_len > threshold)
+            && self.reseed.last_buckets != Some(self.buckets.len())
+    }
+
+    fn reseed_and_rehash(&mut self, chain_len: usize) {
+        // Not zero, which would mean no seed.
+        self.seed = new_seed(self.seed, self.buckets.as_ptr() as u64).max(1);
+        // Not `capacity()`, which is made up for ZSTs.
+        self.resize(self.buckets.len());
+        self.reseed.count += 1;
+        self.reseed.last_buckets = Some(self.buckets.len());
+        if let Some(callback) = self.reseed.callback {
+            callback(&ReseedEvent {
+                chain_len,
+                longest_chain: self.reseed.longest_chain,
+                len: self.len,
+                count: self.reseed.count,
             });
         }
     }
 
-    /// Hashes the key, mod the hash by the number of buckets.
-    /// Returns `None` if capacity is zero.
-    fn index(&self, key: &K) -> Option<usize> {
-        let hash = hash(DefaultHasher::new(), key);
-        (hash as usize).checked_rem(self.buckets.len())
+    /// Number of slots in the overflow arena, including vacant ones.
+    /// This function is `pub(crate)` for use in testing.
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn arena_slots(&self) -> usize {
+        self.arena.slots.len()
     }
 
-    /// The bucket for a key.
-    /// Returns `None` if capacity is zero.
-    fn bucket<'a>(&'a self, key: &K) -> Option<&'a Bucket<K, V>> {
-        let idx = self.index(key)?;
-        Some(&self.buckets[idx])
+    /// Number of buckets whose colliding entries are kept sorted.
+    /// This function is `pub(crate)` for use in testing.
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn treeified_buckets(&self) -> usize {
+        self.buckets
+            .iter()
+            .filter(|bucket| matches!(bucket.others, Option_::Some(Overflow::Sorted { .. })))
+            .count()
     }
 
-    /// The bucket for a key.
+    /// Hashes the key, mod the hash by the number of buckets.
     /// Returns `None` if capacity is zero.
-    fn bucket_mut<'a>(&'a mut self, key: &K) -> Option<&'a mut Bucket<K, V>> {
-        let idx = self.index(key)?;
-        Some(&mut self.buckets[idx])
+    fn index(&self, key: &K) -> Option<usize> {
+        bucket_index(&self.hash_builder, self.seed, key, self.buckets.len())
     }
 
     pub fn get_kv<'a>(&'a self, key: &K) -> Option<(&'a K, &'a V)> {
-        self.bucket(key)?.get(key)
+        let idx = self.index(key)?;
+        self.buckets[idx].get(&self.arena, key)
     }
 
     pub fn get<'a>(&'a self, key: &K) -> Option<&'a V> {
@@ -398,28 +1206,96 @@ where
     }
 
     pub fn get_mut_kv<'a>(&'a mut self, key: &K) -> Option<(&'a mut K, &'a mut V)> {
-        self.bucket_mut(key)?.get_mut(key)
+        let idx = self.index(key)?;
+        self.buckets[idx].get_mut(&mut self.arena, key)
     }
 
     pub fn get_mut<'a>(&'a mut self, key: &K) -> Option<&'a mut V> {
         self.get_mut_kv(key).map(|(_, v)| v)
     }
 
-    pub fn remove(&mut self, key: &K) -> Option<V> {
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_kv(key).is_some()
+    }
+
+    pub fn remove_kv(&mut self, key: &K) -> Option<(K, V)> {
+        let idx = self.index(key)?;
+        let kv = self.buckets[idx].remove(&mut self.arena, key)?;
         self.len -= 1;
-        self.bucket_mut(key)?.remove(key)
+        Some(kv)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_kv(key).map(|(_, v)| v)
     }
 
     pub fn insert_kv(&mut self, key: K, value: V) -> Option<(K, V)> {
-        self.len += 1;
         self.expand_if_needed();
-        self.bucket_mut(&key)?.insert(key, value)
+        let idx = self.index(&key)?;
+        let treeify = self.treeify.map(|cmp| (cmp, self.allocator().clone()));
+        let bucket = &mut self.buckets[idx];
+        let old = bucket.insert(&mut self.arena, treeify, key, value);
+        if old.is_none() {
+            self.len += 1;
+            let chain_len = bucket.len();
+            self.reseed_if_needed(chain_len);
+        }
+        old
     }
 
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
         self.insert_kv(key, value).map(|(_, v)| v)
     }
 
+    /// Like `insert`, also returns a `Handle` for getting at the entry without hashing.
+    pub fn insert_with_handle(&mut self, key: K, value: V) -> (Handle, Option<V>) {
+        self.expand_if_needed();
+        let mut idx = self.index(&key).unwrap();
+        // Reseeding moves the entries, so it's done before inserting rather than after.
+        let chain_len = self.buckets[idx].len() + 1;
+        if self.needs_reseed(chain_len) && !self.contains_key(&key) {
+            self.reseed_and_rehash(chain_len);
+            idx = self.index(&key).unwrap();
+        }
+        let treeify = self.treeify.map(|cmp| (cmp, self.allocator().clone()));
+        let bucket = &mut self.buckets[idx];
+        let (slot, old) = bucket.insert_located(&mut self.arena, treeify, key, value);
+        if old.is_none() {
+            self.len += 1;
+            self.reseed.longest_chain = usize::max(self.reseed.longest_chain, bucket.len());
+        }
+        (self.handle_at(idx, slot), old.map(|(_, v)| v))
+    }
+
+    /// A `Handle` to the entry of the key.
+    pub fn handle(&self, key: &K) -> Option<Handle> {
+        let idx = self.index(key)?;
+        let bucket = &self.buckets[idx];
+        let slot = match bucket.first() {
+            Some((k, _)) if k == key => None,
+            _ => Some(bucket.find_in_overflow(&self.arena, key)?),
+        };
+        Some(self.handle_at(idx, slot))
+    }
+
+    /// Inserts even if the key is already there, after the entries with the same key. Lookups
+    /// find the oldest of them. For `MultiMap`.
+    pub(crate) fn insert_duplicate(&mut self, key: K, value: V) {
+        self.expand_if_needed();
+        let idx = self.index(&key).unwrap();
+        let treeify = self.treeify.map(|cmp| (cmp, self.allocator().clone()));
+        self.buckets[idx].push(&mut self.arena, treeify, key, value);
+        self.len += 1;
+    }
+
+    /// The entries of the bucket `key` would be in.
+    pub(crate) fn bucket_of(&self, key: &K) -> BucketIter<'_, K, V> {
+        match self.index(key) {
+            Some(idx) => self.buckets[idx].iter(&self.arena.slots),
+            None => BucketIter::empty(),
+        }
+    }
+
     pub fn reserve(&mut self, additional: usize) {
         // FIXME: Reserve more aggressively here.
         self.reserve_exact(additional);
@@ -436,195 +1312,124 @@ where
         self.shrink_to(0)
     }
 
+    /// Keeps only the entries for which `f` returns true. Rehashes the remaining entries, so it
+    /// takes about as long as a resize.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.rebuild(self.buckets.len(), f);
+    }
+
     pub fn shrink_to(&mut self, min_capacity: usize) {
         let needed_capacity = (self.len() as f64 / LOAD_FACTOR_MAX) as usize;
         self.resize(usize::max(needed_capacity, min_capacity));
     }
 }
 
-#[derive(Clone)]
-struct BucketIter<'a, K, V> {
-    first: option::IntoIter<&'a (K, V)>,
-    others: slice::Iter<'a, (K, V)>,
-}
-
-impl<'a, K, V> BucketIter<'a, K, V> {
-    fn new(first: Option<&'a (K, V)>, others: &'a [(K, V)]) -> Self {
-        Self {
-            first: first.into_iter(),
-            others: others.iter(),
-        }
-    }
-}
-
-impl<'a, K, V> Iterator for BucketIter<'a, K, V> {
-    type Item = (&'a K, &'a V);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some((k, v)) = self.first.next() {
-            return Some((k, v));
-        }
-        self.others.next().map(|(k, v)| (k, v))
-    }
-}
-
-struct BucketIterMut<'a, K, V> {
-    first: option::IntoIter<&'a mut (K, V)>,
-    others: slice::IterMut<'a, (K, V)>,
-}
-
-impl<'a, K, V> BucketIterMut<'a, K, V> {
-    fn new(first: Option<&'a mut (K, V)>, others: &'a mut [(K, V)]) -> Self {
-        Self {
-            first: first.into_iter(),
-            others: others.iter_mut(),
-        }
-    }
-}
-
-impl<'a, K, V> Iterator for BucketIterMut<'a, K, V> {
-    type Item = (&'a mut K, &'a mut V);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some((k, v)) = self.first.next() {
-            return Some((k, v));
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Hash + Ord,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    /// Keeps the colliding entries of a bucket sorted once there are more than a few of them,
+    /// like Java's `HashMap` turning long chains into trees. Lookups in such a bucket take
+    /// O(log n) instead of O(n), even if keys are crafted to collide.
+    ///
+    /// Buckets are turned back into chains when they shrink.
+    pub fn enable_treeify(&mut self) {
+        let cmp: KeyCmp<K> = K::cmp;
+        self.treeify = Some(cmp);
+        let alloc = self.allocator().clone();
+        for bucket in &mut self.buckets {
+            bucket.treeify_if_needed(&mut self.arena, cmp, alloc.clone());
         }
-        self.others.next().map(|(k, v)| (k, v))
     }
 }
 
-#[derive(Clone)]
-struct BucketIntoIter<K, V> {
-    first: option::IntoIter<(K, V)>,
-    others: vec::IntoIter<(K, V)>,
+/// Iterators over a map yield the first entry of every bucket, then the entries in the arena.
+pub struct Iter<'a, K, V, A: Allocator = Global> {
+    buckets: slice::Iter<'a, Bucket<K, V, A>>,
+    slots: slice::Iter<'a, Slot<K, V>>,
 }
 
-impl<K, V> BucketIntoIter<K, V> {
-    fn new(first: Option<(K, V)>, others: Vec<(K, V)>) -> Self {
+impl<K, V, A: Allocator> Clone for Iter<'_, K, V, A> {
+    fn clone(&self) -> Self {
         Self {
-            first: first.into_iter(),
-            others: others.into_iter(),
-        }
-    }
-}
-
-impl<K, V> Iterator for BucketIntoIter<K, V> {
-    type Item = (K, V);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some((k, v)) = self.first.next() {
-            return Some((k, v));
+            buckets: self.buckets.clone(),
+            slots: self.slots.clone(),
         }
-        self.others.next()
     }
 }
 
-#[derive(Clone)]
-pub struct Iter<'a, K, V> {
-    buckets: slice::Iter<'a, Bucket<K, V>>,
-    current_bucket: Option<BucketIter<'a, K, V>>,
-}
-
-impl<'a, K, V> Iter<'a, K, V> {
-    fn new(buckets: &'a [Bucket<K, V>]) -> Self {
+impl<'a, K, V, A: Allocator> Iter<'a, K, V, A> {
+    pub(crate) fn new(buckets: &'a [Bucket<K, V, A>], slots: &'a [Slot<K, V>]) -> Self {
         Self {
             buckets: buckets.iter(),
-            current_bucket: None,
+            slots: slots.iter(),
         }
     }
 }
 
-impl<'a, K, V> Iterator for Iter<'a, K, V> {
+impl<'a, K, V, A: Allocator> Iterator for Iter<'a, K, V, A> {
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match &mut self.current_bucket {
-                Some(bucket_iter) => match bucket_iter.next() {
-                    Some(kv) => break Some(kv),
-                    None => {
-                        self.current_bucket = self.buckets.next().map(Bucket::iter);
-                        continue;
-                    }
-                },
-                None => {
-                    self.current_bucket = Some(self.buckets.next().map(Bucket::iter)?);
-                    continue;
-                }
-            }
-        }
+        self.buckets
+            .by_ref()
+            .find_map(Bucket::first)
+            .or_else(|| self.slots.find_map(Slot::entry))
     }
 }
 
-pub struct IterMut<'a, K, V> {
-    buckets: slice::IterMut<'a, Bucket<K, V>>,
-    current_bucket: Option<BucketIterMut<'a, K, V>>,
+pub struct IterMut<'a, K, V, A: Allocator = Global> {
+    buckets: slice::IterMut<'a, Bucket<K, V, A>>,
+    slots: slice::IterMut<'a, Slot<K, V>>,
 }
 
-impl<'a, K, V> IterMut<'a, K, V> {
-    fn new(buckets: &'a mut [Bucket<K, V>]) -> Self {
+impl<'a, K, V, A: Allocator> IterMut<'a, K, V, A> {
+    pub(crate) fn new(buckets: &'a mut [Bucket<K, V, A>], slots: &'a mut [Slot<K, V>]) -> Self {
         Self {
             buckets: buckets.iter_mut(),
-            current_bucket: None,
+            slots: slots.iter_mut(),
         }
     }
 }
 
-impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+impl<'a, K, V, A: Allocator> Iterator for IterMut<'a, K, V, A> {
     type Item = (&'a mut K, &'a mut V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match &mut self.current_bucket {
-                Some(bucket_iter) => match bucket_iter.next() {
-                    Some(kv) => break Some(kv),
-                    None => {
-                        self.current_bucket = self.buckets.next().map(Bucket::iter_mut);
-                        continue;
-                    }
-                },
-                None => {
-                    self.current_bucket = Some(self.buckets.next().map(Bucket::iter_mut)?);
-                    continue;
-                }
-            }
-        }
+        self.buckets
+            .by_ref()
+            .find_map(Bucket::first_mut)
+            .or_else(|| self.slots.find_map(Slot::entry_mut))
     }
 }
 
 #[derive(Clone)]
-pub struct IntoIter<K, V> {
-    buckets: vec::IntoIter<Bucket<K, V>>,
-    current_bucket: Option<BucketIntoIter<K, V>>,
+pub struct IntoIter<K, V, A: Allocator = Global> {
+    buckets: vec::IntoIter<Bucket<K, V, A>, A>,
+    slots: vec::IntoIter<Slot<K, V>, A>,
 }
 
-impl<K, V> IntoIter<K, V> {
-    fn new(buckets: Vec<Bucket<K, V>>) -> Self {
+impl<K, V, A: Allocator> IntoIter<K, V, A> {
+    fn new(buckets: Vec<Bucket<K, V, A>, A>, slots: Vec<Slot<K, V>, A>) -> Self {
         Self {
             buckets: buckets.into_iter(),
-            current_bucket: None,
+            slots: slots.into_iter(),
         }
     }
 }
 
-impl<K, V> Iterator for IntoIter<K, V> {
+impl<K, V, A: Allocator> Iterator for IntoIter<K, V, A> {
     type Item = (K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match &mut self.current_bucket {
-                Some(bucket_iter) => match bucket_iter.next() {
-                    Some(kv) => break Some(kv),
-                    None => {
-                        self.current_bucket = self.buckets.next().map(Bucket::into_iter);
-                        continue;
-                    }
-                },
-                None => {
-                    self.current_bucket = Some(self.buckets.next().map(Bucket::into_iter)?);
-                    continue;
-                }
-            }
-        }
+        self.buckets
+            .by_ref()
+            .find_map(|bucket| bucket.first.into_option())
+            .or_else(|| self.slots.find_map(|slot| slot.entry.into_option()))
     }
 }
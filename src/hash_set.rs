@@ -1,22 +1,25 @@
-use std::{
+use core::{
     fmt::{self, Debug},
-    hash::Hash,
+    hash::{BuildHasher, Hash},
 };
 
-use crate::hash_map::{self, HashMap};
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::hash_map::{self, DefaultHashBuilder, HashMap};
 
 #[derive(Clone)]
-pub struct HashSet<T> {
-    map: HashMap<T, ()>,
+pub struct HashSet<T, S = DefaultHashBuilder, A: Allocator = Global> {
+    pub(crate) map: HashMap<T, (), S, A>,
 }
 
-impl<T: Debug> Debug for HashSet<T> {
+impl<T: Debug, S, A: Allocator> Debug for HashSet<T, S, A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_set().entries(self.iter()).finish()
     }
 }
 
-impl<T> HashSet<T> {
+#[cfg(feature = "std")]
+impl<T> HashSet<T, DefaultHashBuilder> {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
@@ -28,25 +31,93 @@ impl<T> HashSet<T> {
             map: HashMap::with_capacity(capacity),
         }
     }
+}
+
+#[cfg(feature = "std")]
+impl<T, A: Allocator + Clone> HashSet<T, DefaultHashBuilder, A> {
+    pub fn new_in(alloc: A) -> Self {
+        Self {
+            map: HashMap::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self {
+            map: HashMap::with_capacity_in(capacity, alloc),
+        }
+    }
+}
+
+impl<T, S> HashSet<T, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hash_builder),
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_capacity_and_hasher(capacity, hash_builder),
+        }
+    }
+}
+
+impl<T, S, A: Allocator + Clone> HashSet<T, S, A> {
+    pub fn with_hasher_in(hash_builder: S, alloc: A) -> Self {
+        Self {
+            map: HashMap::with_hasher_in(hash_builder, alloc),
+        }
+    }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn with_capacity_and_hasher_in(capacity: usize, hash_builder: S, alloc: A) -> Self {
+        Self {
+            map: HashMap::with_capacity_and_hasher_in(capacity, hash_builder, alloc),
+        }
+    }
+}
+
+impl<T, S, A: Allocator> HashSet<T, S, A> {
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.map.allocator()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, A> {
         self.into_iter()
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, A> {
         self.into_iter()
     }
 }
 
-impl<T> Default for HashSet<T> {
+impl<T, S> Default for HashSet<T, S>
+where
+    S: Default,
+{
     fn default() -> Self {
-        Self::new()
+        Self::with_hasher(S::default())
     }
 }
 
-impl<'a, T> IntoIterator for &'a HashSet<T> {
+impl<'a, T, S, A: Allocator> IntoIterator for &'a HashSet<T, S, A> {
     type Item = &'a T;
-    type IntoIter = Iter<'a, T>;
+    type IntoIter = Iter<'a, T, A>;
     fn into_iter(self) -> Self::IntoIter {
         Iter {
             inner: self.map.iter(),
@@ -54,9 +125,9 @@ impl<'a, T> IntoIterator for &'a HashSet<T> {
     }
 }
 
-impl<'a, T> IntoIterator for &'a mut HashSet<T> {
+impl<'a, T, S, A: Allocator> IntoIterator for &'a mut HashSet<T, S, A> {
     type Item = &'a mut T;
-    type IntoIter = IterMut<'a, T>;
+    type IntoIter = IterMut<'a, T, A>;
     fn into_iter(self) -> Self::IntoIter {
         IterMut {
             inner: self.map.iter_mut(),
@@ -64,9 +135,9 @@ impl<'a, T> IntoIterator for &'a mut HashSet<T> {
     }
 }
 
-impl<T> IntoIterator for HashSet<T> {
+impl<T, S, A: Allocator> IntoIterator for HashSet<T, S, A> {
     type Item = T;
-    type IntoIter = IntoIter<T>;
+    type IntoIter = IntoIter<T, A>;
     fn into_iter(self) -> Self::IntoIter {
         IntoIter {
             inner: self.map.into_iter(),
@@ -74,9 +145,11 @@ impl<T> IntoIterator for HashSet<T> {
     }
 }
 
-impl<T> HashSet<T>
+impl<T, S, A> HashSet<T, S, A>
 where
     T: Hash + Eq,
+    S: BuildHasher,
+    A: Allocator + Clone,
 {
     pub fn get<'a>(&'a self, key: &T) -> Option<&'a T> {
         self.map.get_kv(key).map(|(k, ())| k)
@@ -107,23 +180,30 @@ where
     }
 }
 
-#[derive(Clone)]
-pub struct Iter<'a, T> {
-    inner: hash_map::Iter<'a, T, ()>,
+pub struct Iter<'a, T, A: Allocator = Global> {
+    pub(crate) inner: hash_map::Iter<'a, T, (), A>,
+}
+
+impl<T, A: Allocator> Clone for Iter<'_, T, A> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
+impl<'a, T, A: Allocator> Iterator for Iter<'a, T, A> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next().map(|(k, _)| k)
     }
 }
 
-pub struct IterMut<'a, T> {
-    inner: hash_map::IterMut<'a, T, ()>,
+pub struct IterMut<'a, T, A: Allocator = Global> {
+    pub(crate) inner: hash_map::IterMut<'a, T, (), A>,
 }
 
-impl<'a, T> Iterator for IterMut<'a, T> {
+impl<'a, T, A: Allocator> Iterator for IterMut<'a, T, A> {
     type Item = &'a mut T;
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next().map(|(k, _)| k)
@@ -131,11 +211,11 @@ impl<'a, T> Iterator for IterMut<'a, T> {
 }
 
 #[derive(Clone)]
-pub struct IntoIter<T> {
-    inner: hash_map::IntoIter<T, ()>,
+pub struct IntoIter<T, A: Allocator = Global> {
+    inner: hash_map::IntoIter<T, (), A>,
 }
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
     type Item = T;
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next().map(|(k, _)| k)
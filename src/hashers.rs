@@ -0,0 +1,247 @@
+//! Fast non-cryptographic hashers, each behind its own cargo feature.
+//!
+//! The default hasher of `HashMap` is SipHash, which is slow for small keys. The hashers here trade
+//! some or all of its HashDoS resistance for speed:
+//!
+//! - `FxHasher` (feature `fxhash`): the hasher used in rustc. Fastest, but unkeyed, so anyone who
+//!   controls the keys can make them collide.
+//! - `FnvHasher` (feature `fnv`): FNV-1a. Unkeyed, good for short keys.
+//! - `QuickHasher` (feature `quickhash`): a keyed hasher based on folded multiplication, in the
+//!   spirit of AHash's fallback algorithm. Not cryptographically secure, but the keys are random
+//!   per `QuickState`. Without the `std` feature, keys have to be provided with
+//!   `QuickState::with_seeds`.
+//!
+//! All of them work without `std`.
+
+#[cfg(feature = "fxhash")]
+pub use fx::*;
+
+#[cfg(feature = "fxhash")]
+mod fx {
+    use core::hash::{BuildHasherDefault, Hasher};
+
+    use crate::{hash_map::HashMap, hash_set::HashSet};
+
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+    /// The hasher used in rustc.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct FxHasher {
+        hash: u64,
+    }
+
+    pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+    pub type FxHashMap<K, V> = HashMap<K, V, FxBuildHasher>;
+    pub type FxHashSet<T> = HashSet<T, FxBuildHasher>;
+
+    impl FxHasher {
+        fn add_to_hash(&mut self, i: u64) {
+            self.hash = (self.hash.rotate_left(5) ^ i).wrapping_mul(SEED);
+        }
+    }
+
+    impl Hasher for FxHasher {
+        fn write(&mut self, mut bytes: &[u8]) {
+            while let Some((chunk, rest)) = bytes.split_first_chunk::<8>() {
+                self.add_to_hash(u64::from_le_bytes(*chunk));
+                bytes = rest;
+            }
+            if let Some((chunk, rest)) = bytes.split_first_chunk::<4>() {
+                self.add_to_hash(u32::from_le_bytes(*chunk) as u64);
+                bytes = rest;
+            }
+            if let Some((chunk, rest)) = bytes.split_first_chunk::<2>() {
+                self.add_to_hash(u16::from_le_bytes(*chunk) as u64);
+                bytes = rest;
+            }
+            if let Some(&byte) = bytes.first() {
+                self.add_to_hash(byte as u64);
+            }
+        }
+
+        fn write_u8(&mut self, i: u8) {
+            self.add_to_hash(i as u64);
+        }
+
+        fn write_u16(&mut self, i: u16) {
+            self.add_to_hash(i as u64);
+        }
+
+        fn write_u32(&mut self, i: u32) {
+            self.add_to_hash(i as u64);
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.add_to_hash(i);
+        }
+
+        fn write_usize(&mut self, i: usize) {
+            self.add_to_hash(i as u64);
+        }
+
+        fn finish(&self) -> u64 {
+            // The multiply only carries upwards, so the low bits of the state depend only on the
+            // low bits of the input. Buckets are picked by the low bits, rotate the high ones in,
+            // like rustc-hash 2 does.
+            self.hash.rotate_left(26)
+        }
+    }
+}
+
+#[cfg(feature = "fnv")]
+pub use fnv::*;
+
+#[cfg(feature = "fnv")]
+mod fnv {
+    use core::hash::{BuildHasherDefault, Hasher};
+
+    use crate::{hash_map::HashMap, hash_set::HashSet};
+
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    /// 64-bit FNV-1a.
+    #[derive(Debug, Clone, Copy)]
+    pub struct FnvHasher {
+        hash: u64,
+    }
+
+    pub type FnvBuildHasher = BuildHasherDefault<FnvHasher>;
+    pub type FnvHashMap<K, V> = HashMap<K, V, FnvBuildHasher>;
+    pub type FnvHashSet<T> = HashSet<T, FnvBuildHasher>;
+
+    impl Default for FnvHasher {
+        fn default() -> Self {
+            Self { hash: OFFSET_BASIS }
+        }
+    }
+
+    impl Hasher for FnvHasher {
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.hash ^= byte as u64;
+                self.hash = self.hash.wrapping_mul(PRIME);
+            }
+        }
+
+        fn finish(&self) -> u64 {
+            self.hash
+        }
+    }
+}
+
+#[cfg(feature = "quickhash")]
+pub use quick::*;
+
+#[cfg(feature = "quickhash")]
+mod quick {
+    use core::hash::{BuildHasher, Hasher};
+
+    use crate::{hash_map::HashMap, hash_set::HashSet};
+
+    /// From PCG.
+    const MULTIPLE: u64 = 6364136223846793005;
+
+    fn folded_multiply(x: u64, y: u64) -> u64 {
+        let full = (x as u128).wrapping_mul(y as u128);
+        (full as u64) ^ ((full >> 64) as u64)
+    }
+
+    /// A keyed hasher based on folded multiplication.
+    #[derive(Debug, Clone, Copy)]
+    pub struct QuickHasher {
+        buffer: u64,
+        pad: u64,
+    }
+
+    impl QuickHasher {
+        fn update(&mut self, i: u64) {
+            self.buffer = folded_multiply(self.buffer ^ i, MULTIPLE);
+        }
+    }
+
+    impl Hasher for QuickHasher {
+        fn write(&mut self, mut bytes: &[u8]) {
+            self.buffer = self.buffer.wrapping_add(bytes.len() as u64);
+            while let Some((chunk, rest)) = bytes.split_first_chunk::<8>() {
+                self.update(u64::from_le_bytes(*chunk));
+                bytes = rest;
+            }
+            if !bytes.is_empty() {
+                let mut tail = [0u8; 8];
+                tail[..bytes.len()].copy_from_slice(bytes);
+                self.update(u64::from_le_bytes(tail));
+            }
+        }
+
+        fn write_u8(&mut self, i: u8) {
+            self.update(i as u64);
+        }
+
+        fn write_u16(&mut self, i: u16) {
+            self.update(i as u64);
+        }
+
+        fn write_u32(&mut self, i: u32) {
+            self.update(i as u64);
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.update(i);
+        }
+
+        fn write_usize(&mut self, i: usize) {
+            self.update(i as u64);
+        }
+
+        fn finish(&self) -> u64 {
+            let rot = (self.buffer & 63) as u32;
+            folded_multiply(self.buffer, self.pad).rotate_left(rot)
+        }
+    }
+
+    /// Builds `QuickHasher`s with the same keys.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct QuickState {
+        k0: u64,
+        k1: u64,
+    }
+
+    pub type QuickHashMap<K, V> = HashMap<K, V, QuickState>;
+    pub type QuickHashSet<T> = HashSet<T, QuickState>;
+
+    impl QuickState {
+        /// With random keys.
+        #[cfg(feature = "std")]
+        pub fn new() -> Self {
+            let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            hasher.write_u8(0);
+            let k0 = hasher.finish();
+            hasher.write_u8(1);
+            let k1 = hasher.finish();
+            Self::with_seeds(k0, k1)
+        }
+
+        pub const fn with_seeds(k0: u64, k1: u64) -> Self {
+            Self { k0, k1 }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl Default for QuickState {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl BuildHasher for QuickState {
+        type Hasher = QuickHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            QuickHasher {
+                buffer: self.k0,
+                pad: self.k1,
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! A map that remembers the order its entries were inserted in.
+
+use alloc::vec::{self, Vec};
+use core::{
+    cmp::Ordering,
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    ops::Index,
+    slice,
+};
+
+use crate::{hash_map::DefaultHashBuilder, raw_index::RawIndex};
+
+/// Keeps the entries in a dense `Vec`, in insertion order, and a hash table of positions into it.
+/// Iteration goes over the `Vec`, so it's deterministic and fast, and every entry also has an
+/// index, see `get_index`.
+///
+/// Removing keeps the order with `shift_remove`, which is O(n), or moves the last entry into the
+/// hole with `swap_remove`, which is O(1).
+#[derive(Clone)]
+pub struct IndexMap<K, V, S = DefaultHashBuilder> {
+    hash_builder: S,
+    entries: Vec<Entry<K, V>>,
+    index: RawIndex,
+}
+
+#[derive(Clone)]
+struct Entry<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+impl<K: Debug, V: Debug, S> Debug for IndexMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> IndexMap<K, V, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> IndexMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            hash_builder,
+            entries: Vec::new(),
+            index: RawIndex::new(),
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            hash_builder,
+            entries: Vec::with_capacity(capacity),
+            index: RawIndex::with_capacity(capacity),
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    /// In insertion order, or the order set by `sort_by` and `move_index`.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            inner: self.entries.iter_mut(),
+        }
+    }
+
+    pub fn get_index(&self, idx: usize) -> Option<(&K, &V)> {
+        self.entries.get(idx).map(|e| (&e.key, &e.value))
+    }
+
+    pub fn get_index_mut(&mut self, idx: usize) -> Option<(&K, &mut V)> {
+        self.entries.get_mut(idx).map(|e| (&e.key, &mut e.value))
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.get_index(0)
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.get_index(self.len().checked_sub(1)?)
+    }
+
+    /// Sorts the entries, stable. The hash table is rebuilt afterwards, which is O(n).
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&K, &V, &K, &V) -> Ordering,
+    {
+        self.entries
+            .sort_by(|a, b| compare(&a.key, &a.value, &b.key, &b.value));
+        self.index.rebuild(self.entries.iter().map(|e| e.hash));
+    }
+
+    /// Moves the entry at `from` to `to`, shifting the entries in between by one. O(n).
+    ///
+    /// Panics if either is out of bounds.
+    pub fn move_index(&mut self, from: usize, to: usize) {
+        assert!(from < self.len() && to < self.len(), "index out of bounds");
+        match from.cmp(&to) {
+            Ordering::Less => {
+                self.entries[from..=to].rotate_left(1);
+                self.index.update_all(|i| match i {
+                    _ if i == from => to,
+                    _ if (from + 1..=to).contains(&i) => i - 1,
+                    _ => i,
+                });
+            }
+            Ordering::Greater => {
+                self.entries[to..=from].rotate_right(1);
+                self.index.update_all(|i| match i {
+                    _ if i == from => to,
+                    _ if (to..from).contains(&i) => i + 1,
+                    _ => i,
+                });
+            }
+            Ordering::Equal => (),
+        }
+    }
+
+    /// Removes the entry at `idx` and fills the hole with the last entry, O(1).
+    pub fn swap_remove_index(&mut self, idx: usize) -> Option<(K, V)> {
+        if idx >= self.len() {
+            return None;
+        }
+        let last = self.len() - 1;
+        self.index.remove(self.entries[idx].hash, idx);
+        if idx != last {
+            self.index.replace(self.entries[last].hash, last, idx);
+        }
+        let entry = self.entries.swap_remove(idx);
+        Some((entry.key, entry.value))
+    }
+
+    /// Removes the entry at `idx` and shifts the following entries down, O(n).
+    pub fn shift_remove_index(&mut self, idx: usize) -> Option<(K, V)> {
+        if idx >= self.len() {
+            return None;
+        }
+        self.index.remove(self.entries[idx].hash, idx);
+        self.index.update_all(|i| if i > idx { i - 1 } else { i });
+        let entry = self.entries.remove(idx);
+        Some((entry.key, entry.value))
+    }
+}
+
+impl<K, V, S> Default for IndexMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn find(&self, hash: u64, key: &K) -> Option<usize> {
+        self.index.find(hash, |i| self.entries[i].key == *key)
+    }
+
+    pub fn get_index_of(&self, key: &K) -> Option<usize> {
+        self.find(self.hash_builder.hash_one(key), key)
+    }
+
+    pub fn get_kv<'a>(&'a self, key: &K) -> Option<(&'a K, &'a V)> {
+        self.get_index(self.get_index_of(key)?)
+    }
+
+    pub fn get<'a>(&'a self, key: &K) -> Option<&'a V> {
+        self.get_kv(key).map(|(_, v)| v)
+    }
+
+    /// The key isn't handed out mutably, the entry keeps the hash of the key.
+    pub fn get_mut_kv<'a>(&'a mut self, key: &K) -> Option<(&'a K, &'a mut V)> {
+        let idx = self.get_index_of(key)?;
+        self.get_index_mut(idx)
+    }
+
+    pub fn get_mut<'a>(&'a mut self, key: &K) -> Option<&'a mut V> {
+        self.get_mut_kv(key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_index_of(key).is_some()
+    }
+
+    /// A new key goes at the end. An existing key keeps its position, the key and value are
+    /// replaced and the old ones returned.
+    pub fn insert_kv(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.insert_full(key, value).1
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_kv(key, value).map(|(_, v)| v)
+    }
+
+    /// Like `insert_kv`, also returns the position of the entry.
+    pub fn insert_full(&mut self, key: K, value: V) -> (usize, Option<(K, V)>) {
+        let hash = self.hash_builder.hash_one(&key);
+        match self.find(hash, &key) {
+            Some(idx) => {
+                let entry = &mut self.entries[idx];
+                let old_key = core::mem::replace(&mut entry.key, key);
+                let old_value = core::mem::replace(&mut entry.value, value);
+                (idx, Some((old_key, old_value)))
+            }
+            None => {
+                let idx = self.entries.len();
+                self.index.insert(hash, idx);
+                self.entries.push(Entry { hash, key, value });
+                (idx, None)
+            }
+        }
+    }
+
+    /// Removes the entry in O(1) by moving the last entry into its place, which changes the order.
+    pub fn swap_remove_kv(&mut self, key: &K) -> Option<(K, V)> {
+        let idx = self.get_index_of(key)?;
+        self.swap_remove_index(idx)
+    }
+
+    pub fn swap_remove(&mut self, key: &K) -> Option<V> {
+        self.swap_remove_kv(key).map(|(_, v)| v)
+    }
+
+    /// Removes the entry and keeps the order of the others, O(n).
+    pub fn shift_remove_kv(&mut self, key: &K) -> Option<(K, V)> {
+        let idx = self.get_index_of(key)?;
+        self.shift_remove_index(idx)
+    }
+
+    pub fn shift_remove(&mut self, key: &K) -> Option<V> {
+        self.shift_remove_kv(key).map(|(_, v)| v)
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional);
+        self.index.reserve(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+        self.index.shrink_to(0);
+    }
+}
+
+impl<K, V, S> Index<usize> for IndexMap<K, V, S> {
+    type Output = V;
+
+    /// Panics if `idx` is out of bounds.
+    fn index(&self, idx: usize) -> &V {
+        &self.entries[idx].value
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a IndexMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut IndexMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for IndexMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.entries.into_iter(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Iter<'a, K, V> {
+    inner: slice::Iter<'a, Entry<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|e| (&e.key, &e.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|e| (&e.key, &e.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+pub struct IterMut<'a, K, V> {
+    inner: slice::IterMut<'a, Entry<K, V>>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|e| (&e.key, &mut e.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|e| (&e.key, &mut e.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+#[derive(Clone)]
+pub struct IntoIter<K, V> {
+    inner: vec::IntoIter<Entry<K, V>>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|e| (e.key, e.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|e| (e.key, e.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
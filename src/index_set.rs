@@ -0,0 +1,239 @@
+use core::{
+    cmp::Ordering,
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+};
+
+use crate::{
+    hash_map::DefaultHashBuilder,
+    index_map::{self, IndexMap},
+};
+
+/// A set that remembers the order its elements were inserted in, see `IndexMap`.
+#[derive(Clone)]
+pub struct IndexSet<T, S = DefaultHashBuilder> {
+    map: IndexMap<T, (), S>,
+}
+
+impl<T: Debug, S> Debug for IndexSet<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> IndexSet<T, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self {
+            map: IndexMap::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: IndexMap::with_capacity(capacity),
+        }
+    }
+}
+
+impl<T, S> IndexSet<T, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: IndexMap::with_hasher(hash_builder),
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            map: IndexMap::with_capacity_and_hasher(capacity, hash_builder),
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            inner: self.map.iter(),
+        }
+    }
+
+    pub fn get_index(&self, idx: usize) -> Option<&T> {
+        self.map.get_index(idx).map(|(k, ())| k)
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.map.first().map(|(k, ())| k)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.map.last().map(|(k, ())| k)
+    }
+
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        self.map.sort_by(|a, (), b, ()| compare(a, b));
+    }
+
+    pub fn move_index(&mut self, from: usize, to: usize) {
+        self.map.move_index(from, to);
+    }
+}
+
+impl<T, S> Default for IndexSet<T, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self {
+            map: IndexMap::default(),
+        }
+    }
+}
+
+impl<T, S> IndexSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn get(&self, key: &T) -> Option<&T> {
+        self.map.get_kv(key).map(|(k, ())| k)
+    }
+
+    pub fn get_index_of(&self, key: &T) -> Option<usize> {
+        self.map.get_index_of(key)
+    }
+
+    pub fn contains(&self, key: &T) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// A new element goes at the end, an existing one is replaced in place and returned.
+    pub fn insert(&mut self, key: T) -> Option<T> {
+        self.map.insert_kv(key, ()).map(|(k, ())| k)
+    }
+
+    pub fn insert_full(&mut self, key: T) -> (usize, Option<T>) {
+        let (idx, old) = self.map.insert_full(key, ());
+        (idx, old.map(|(k, ())| k))
+    }
+
+    pub fn swap_remove(&mut self, key: &T) -> Option<T> {
+        self.map.swap_remove_kv(key).map(|(k, ())| k)
+    }
+
+    pub fn shift_remove(&mut self, key: &T) -> Option<T> {
+        self.map.shift_remove_kv(key).map(|(k, ())| k)
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+    }
+}
+
+impl<T, S> FromIterator<T> for IndexSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            map: iter.into_iter().map(|x| (x, ())).collect(),
+        }
+    }
+}
+
+impl<T, S> Extend<T> for IndexSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|x| (x, ())));
+    }
+}
+
+impl<'a, T, S> IntoIterator for &'a IndexSet<T, S> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, S> IntoIterator for IndexSet<T, S> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.map.into_iter(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Iter<'a, T> {
+    inner: index_map::Iter<'a, T, ()>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, ())| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, ())| k)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+#[derive(Clone)]
+pub struct IntoIter<T> {
+    inner: index_map::IntoIter<T, ()>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, ())| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, ())| k)
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
@@ -0,0 +1,463 @@
+//! Maps and sets for integer keys, e.g. entity IDs or file descriptors.
+//!
+//! Unlike `HashMap`, these use a multiplicative hash instead of a `Hasher`, and open addressing
+//! with linear probing. Empty slots are marked by a reserved key (`IntKey::SENTINEL`) instead of
+//! a tag, so an entry takes exactly the size of a key plus a value. The sentinel key itself can
+//! still be used as a key, its value is stored out of line.
+
+use alloc::{vec, vec::Vec};
+use core::{
+    fmt::{self, Debug},
+    iter,
+    mem::{self, MaybeUninit},
+    option, slice,
+};
+
+const LOAD_FACTOR_MAX: f64 = 0.75;
+const INIT_CAPACITY: usize = 8;
+
+/// Fibonacci hashing multiplier, 2^64 / golden ratio.
+const GOLDEN_RATIO: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Integer types usable as keys of `IntMap` and `IntSet`.
+pub trait IntKey: Copy + Eq {
+    /// Marks empty slots.
+    const SENTINEL: Self;
+
+    fn to_u64(self) -> u64;
+}
+
+macro_rules! impl_int_key {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl IntKey for $t {
+                const SENTINEL: Self = <$t>::MAX;
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+            }
+        )*
+    };
+}
+
+impl_int_key!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+pub struct IntMap<K: IntKey, V> {
+    /// `K::SENTINEL` for empty slots, the length is zero or a power of two.
+    keys: Vec<K>,
+    /// Initialized iff the key in the same slot isn't `K::SENTINEL`.
+    values: Vec<MaybeUninit<V>>,
+    /// Number of entries in `keys` and `values`.
+    len: usize,
+    /// Value of the key `K::SENTINEL`.
+    sentinel: Option<V>,
+}
+
+impl<K: IntKey, V> IntMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            values: Vec::new(),
+            len: 0,
+            sentinel: None,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut map = Self::new();
+        map.reserve(capacity);
+        map
+    }
+
+    pub fn len(&self) -> usize {
+        self.len + self.sentinel.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        (self.keys.len() as f64 * LOAD_FACTOR_MAX) as usize
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.into_iter()
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        self.into_iter()
+    }
+
+    pub fn clear(&mut self) {
+        for (k, v) in self.keys.iter_mut().zip(&mut self.values) {
+            if *k != K::SENTINEL {
+                *k = K::SENTINEL;
+                // Safety: Slots with non-sentinel keys have initialized values.
+                unsafe { v.assume_init_drop() };
+            }
+        }
+        self.len = 0;
+        self.sentinel = None;
+    }
+
+    /// Index of the slot to start probing from.
+    fn home(&self, key: K) -> usize {
+        let shift = 64 - self.keys.len().trailing_zeros();
+        (key.to_u64().wrapping_mul(GOLDEN_RATIO) >> shift) as usize
+    }
+
+    /// `Ok` with the slot holding `key`, or `Err` with the empty slot where it would be inserted.
+    /// # Panics
+    /// Panics if capacity is zero.
+    fn find(&self, key: K) -> Result<usize, usize> {
+        let mask = self.keys.len() - 1;
+        let mut i = self.home(key);
+        loop {
+            let k = self.keys[i];
+            if k == key {
+                return Ok(i);
+            } else if k == K::SENTINEL {
+                return Err(i);
+            }
+            i = (i + 1) & mask;
+        }
+    }
+
+    fn find_existing(&self, key: K) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        self.find(key).ok()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        if *key == K::SENTINEL {
+            return self.sentinel.as_ref();
+        }
+        let i = self.find_existing(*key)?;
+        // Safety: Slots with non-sentinel keys have initialized values.
+        Some(unsafe { self.values[i].assume_init_ref() })
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if *key == K::SENTINEL {
+            return self.sentinel.as_mut();
+        }
+        let i = self.find_existing(*key)?;
+        // Safety: Slots with non-sentinel keys have initialized values.
+        Some(unsafe { self.values[i].assume_init_mut() })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if key == K::SENTINEL {
+            return self.sentinel.replace(value);
+        }
+        self.reserve(1);
+        match self.find(key) {
+            Ok(i) => {
+                // Safety: Slots with non-sentinel keys have initialized values.
+                Some(mem::replace(
+                    unsafe { self.values[i].assume_init_mut() },
+                    value,
+                ))
+            }
+            Err(i) => {
+                self.keys[i] = key;
+                self.values[i].write(value);
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if *key == K::SENTINEL {
+            return self.sentinel.take();
+        }
+        let mut hole = self.find_existing(*key)?;
+        self.keys[hole] = K::SENTINEL;
+        // Safety: The slot had a non-sentinel key, and is now marked empty.
+        let value = unsafe { self.values[hole].assume_init_read() };
+        self.len -= 1;
+        // Shift following entries back, so that probing doesn't stop early at the hole.
+        let mask = self.keys.len() - 1;
+        let mut i = hole;
+        loop {
+            i = (i + 1) & mask;
+            let k = self.keys[i];
+            if k == K::SENTINEL {
+                break;
+            }
+            let probe_len = i.wrapping_sub(self.home(k)) & mask;
+            if probe_len >= (i.wrapping_sub(hole) & mask) {
+                self.keys[hole] = k;
+                self.keys[i] = K::SENTINEL;
+                self.values.swap(hole, i);
+                hole = i;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len + additional;
+        if needed <= self.capacity() {
+            return;
+        }
+        let mut slots = usize::max(self.keys.len(), INIT_CAPACITY);
+        while (slots as f64 * LOAD_FACTOR_MAX) < needed as f64 {
+            slots *= 2;
+        }
+        self.resize(slots);
+    }
+
+    /// `slots` must be a power of two that fits all entries.
+    fn resize(&mut self, slots: usize) {
+        let old_keys = mem::replace(&mut self.keys, vec![K::SENTINEL; slots]);
+        let mut old_values = mem::take(&mut self.values);
+        self.values.resize_with(slots, MaybeUninit::uninit);
+        for (k, v) in old_keys.into_iter().zip(&mut old_values) {
+            if k != K::SENTINEL {
+                let Err(i) = self.find(k) else { unreachable!() };
+                self.keys[i] = k;
+                // Safety: Slots with non-sentinel keys have initialized values, `old_values` is
+                // dropped without dropping its elements.
+                self.values[i].write(unsafe { v.assume_init_read() });
+            }
+        }
+    }
+}
+
+impl<K: IntKey, V> Drop for IntMap<K, V> {
+    fn drop(&mut self) {
+        if mem::needs_drop::<V>() {
+            self.clear();
+        }
+    }
+}
+
+impl<K: IntKey, V: Clone> Clone for IntMap<K, V> {
+    fn clone(&self) -> Self {
+        let mut values = Vec::with_capacity(self.values.len());
+        for (k, v) in self.keys.iter().zip(&self.values) {
+            values.push(match *k == K::SENTINEL {
+                true => MaybeUninit::uninit(),
+                // Safety: Slots with non-sentinel keys have initialized values.
+                false => MaybeUninit::new(unsafe { v.assume_init_ref() }.clone()),
+            });
+        }
+        Self {
+            keys: self.keys.clone(),
+            values,
+            len: self.len,
+            sentinel: self.sentinel.clone(),
+        }
+    }
+}
+
+impl<K: IntKey + Debug, V: Debug> Debug for IntMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+impl<K: IntKey, V> Default for IntMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: IntKey, V> FromIterator<(K, V)> for IntMap<K, V> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: IntKey, V> Extend<(K, V)> for IntMap<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K: IntKey, V> IntoIterator for &'a IntMap<K, V> {
+    type Item = (K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        Iter {
+            slots: self.keys.iter().zip(self.values.iter()),
+            sentinel: self.sentinel.iter(),
+        }
+    }
+}
+
+impl<'a, K: IntKey, V> IntoIterator for &'a mut IntMap<K, V> {
+    type Item = (K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        IterMut {
+            slots: self.keys.iter().zip(self.values.iter_mut()),
+            sentinel: self.sentinel.iter_mut(),
+        }
+    }
+}
+
+impl<K: IntKey, V> IntoIterator for IntMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+    fn into_iter(mut self) -> Self::IntoIter {
+        IntoIter {
+            slots: mem::take(&mut self.keys)
+                .into_iter()
+                .zip(mem::take(&mut self.values)),
+            sentinel: self.sentinel.take().into_iter(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Iter<'a, K, V> {
+    slots: iter::Zip<slice::Iter<'a, K>, slice::Iter<'a, MaybeUninit<V>>>,
+    sentinel: option::Iter<'a, V>,
+}
+
+impl<'a, K: IntKey, V> Iterator for Iter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots
+            .find(|(k, _)| **k != K::SENTINEL)
+            // Safety: Slots with non-sentinel keys have initialized values.
+            .map(|(k, v)| (*k, unsafe { v.assume_init_ref() }))
+            .or_else(|| Some((K::SENTINEL, self.sentinel.next()?)))
+    }
+}
+
+pub struct IterMut<'a, K, V> {
+    slots: iter::Zip<slice::Iter<'a, K>, slice::IterMut<'a, MaybeUninit<V>>>,
+    sentinel: option::IterMut<'a, V>,
+}
+
+impl<'a, K: IntKey, V> Iterator for IterMut<'a, K, V> {
+    type Item = (K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots
+            .find(|(k, _)| **k != K::SENTINEL)
+            // Safety: Slots with non-sentinel keys have initialized values.
+            .map(|(k, v)| (*k, unsafe { v.assume_init_mut() }))
+            .or_else(|| Some((K::SENTINEL, self.sentinel.next()?)))
+    }
+}
+
+pub struct IntoIter<K: IntKey, V> {
+    slots: iter::Zip<vec::IntoIter<K>, vec::IntoIter<MaybeUninit<V>>>,
+    sentinel: option::IntoIter<V>,
+}
+
+impl<K: IntKey, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots
+            .find(|(k, _)| *k != K::SENTINEL)
+            // Safety: Slots with non-sentinel keys have initialized values, and each slot is
+            // yielded once.
+            .map(|(k, v)| (k, unsafe { v.assume_init() }))
+            .or_else(|| Some((K::SENTINEL, self.sentinel.next()?)))
+    }
+}
+
+impl<K: IntKey, V> Drop for IntoIter<K, V> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// A set of integers, see `IntMap`.
+#[derive(Clone, Default)]
+pub struct IntSet<K: IntKey> {
+    map: IntMap<K, ()>,
+}
+
+impl<K: IntKey + Debug> Debug for IntSet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<K: IntKey> IntSet<K> {
+    pub fn new() -> Self {
+        Self { map: IntMap::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: IntMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Returns `true` if the key wasn't in the set.
+    pub fn insert(&mut self, key: K) -> bool {
+        self.map.insert(key, ()).is_none()
+    }
+
+    /// Returns `true` if the key was in the set.
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.map.remove(key).is_some()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn iter(&self) -> iter::Map<Iter<'_, K, ()>, fn((K, &())) -> K> {
+        self.map.iter().map(|(k, ())| k)
+    }
+}
+
+impl<K: IntKey> FromIterator<K> for IntSet<K> {
+    fn from_iter<T: IntoIterator<Item = K>>(iter: T) -> Self {
+        Self {
+            map: iter.into_iter().map(|k| (k, ())).collect(),
+        }
+    }
+}
+
+impl<K: IntKey> IntoIterator for IntSet<K> {
+    type Item = K;
+    type IntoIter = iter::Map<IntoIter<K, ()>, fn((K, ())) -> K>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter().map(|(k, ())| k)
+    }
+}
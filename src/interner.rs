@@ -0,0 +1,283 @@
+//! A string interner, handing out a small `Symbol` per distinct string.
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::{self, Debug},
+    hash::BuildHasher,
+    ops::Index,
+};
+
+use crate::{hash_map::DefaultHashBuilder, raw_index::RawIndex};
+
+/// Stands for an interned string, only meaningful to the `Interner` that made it. Symbols are
+/// numbered from zero in the order the strings were first interned, so a symbol from another
+/// interner may resolve to an unrelated string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    fn idx(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Maps strings to `Symbol`s and back. The strings are appended to one `String` and never
+/// removed, the hash table only holds hashes and symbols, so every string is stored once.
+#[derive(Clone)]
+pub struct Interner<S = DefaultHashBuilder> {
+    hash_builder: S,
+    strings: String,
+    /// End of every string in `strings`, by symbol. A string starts where the previous one ends.
+    ends: Vec<usize>,
+    index: RawIndex,
+}
+
+impl<S> Debug for Interner<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Interner<DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+
+    /// `capacity` is in strings.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl<S> Interner<S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            hash_builder,
+            strings: String::new(),
+            ends: Vec::with_capacity(capacity),
+            index: RawIndex::with_capacity(capacity),
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Number of distinct strings.
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// The string of the symbol, `None` if no string got that symbol here yet.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        let end = *self.ends.get(symbol.idx())?;
+        let start = match symbol.idx() {
+            0 => 0,
+            i => self.ends[i - 1],
+        };
+        Some(&self.strings[start..end])
+    }
+
+    /// Every string with its symbol, in symbol order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            strings: &self.strings,
+            ends: self.ends.iter().enumerate(),
+            start: 0,
+        }
+    }
+}
+
+impl<S> Default for Interner<S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<S: BuildHasher> Interner<S> {
+    fn find(&self, hash: u64, s: &str) -> Option<Symbol> {
+        let idx = self
+            .index
+            .find(hash, |i| self.resolve(Symbol(i as u32)) == Some(s))?;
+        Some(Symbol(idx as u32))
+    }
+
+    /// The symbol of the string, if it was interned.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.find(self.hash_builder.hash_one(s), s)
+    }
+
+    /// The symbol of the string, interning it if it's new.
+    ///
+    /// # Panics
+    ///
+    /// If there would be more than `u32::MAX` strings.
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        let hash = self.hash_builder.hash_one(s);
+        if let Some(symbol) = self.find(hash, s) {
+            return symbol;
+        }
+        let idx = self.ends.len();
+        let symbol = Symbol(u32::try_from(idx).expect("too many interned strings"));
+        self.strings.push_str(s);
+        self.ends.push(self.strings.len());
+        self.index.insert(hash, idx);
+        symbol
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.ends.reserve(additional);
+        self.index.reserve(additional);
+    }
+}
+
+impl<S> Index<Symbol> for Interner<S> {
+    type Output = str;
+
+    /// # Panics
+    ///
+    /// If no string got the symbol here yet.
+    fn index(&self, symbol: Symbol) -> &str {
+        self.resolve(symbol).expect("symbol out of range")
+    }
+}
+
+impl<'a, S> Extend<&'a str> for Interner<S>
+where
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = &'a str>>(&mut self, iter: T) {
+        for s in iter {
+            self.intern(s);
+        }
+    }
+}
+
+impl<'a, S> FromIterator<&'a str> for Interner<S>
+where
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = &'a str>>(iter: T) -> Self {
+        let mut interner = Self::default();
+        interner.extend(iter);
+        interner
+    }
+}
+
+impl<'a, S> IntoIterator for &'a Interner<S> {
+    type Item = (Symbol, &'a str);
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Clone)]
+pub struct Iter<'a> {
+    strings: &'a str,
+    ends: core::iter::Enumerate<core::slice::Iter<'a, usize>>,
+    start: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (Symbol, &'a str);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, &end) = self.ends.next()?;
+        let s = &self.strings[self.start..end];
+        self.start = end;
+        Some((Symbol(i as u32), s))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ends.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(feature = "std")]
+pub use sync::SyncInterner;
+
+#[cfg(feature = "std")]
+mod sync {
+    use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+
+    use super::*;
+
+    /// An `Interner` behind a `RwLock`, for interning from many threads. Strings that are already
+    /// interned only take the read lock.
+    #[derive(Default)]
+    pub struct SyncInterner<S = DefaultHashBuilder> {
+        inner: RwLock<Interner<S>>,
+    }
+
+    impl<S> Debug for SyncInterner<S> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.read().fmt(f)
+        }
+    }
+
+    impl SyncInterner<DefaultHashBuilder> {
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl<S> SyncInterner<S> {
+        pub fn with_hasher(hash_builder: S) -> Self {
+            Self {
+                inner: RwLock::new(Interner::with_hasher(hash_builder)),
+            }
+        }
+
+        /// Locks the interner for reading, to resolve symbols.
+        pub fn read(&self) -> RwLockReadGuard<'_, Interner<S>> {
+            self.inner.read().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        pub fn into_inner(self) -> Interner<S> {
+            self.inner
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl<S: BuildHasher> SyncInterner<S> {
+        pub fn get(&self, s: &str) -> Option<Symbol> {
+            self.read().get(s)
+        }
+
+        pub fn intern(&self, s: &str) -> Symbol {
+            if let Some(symbol) = self.get(s) {
+                return symbol;
+            }
+            // Another thread may have interned it since, `Interner::intern` checks again.
+            let mut inner = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+            inner.intern(s)
+        }
+    }
+
+    impl<S> From<Interner<S>> for SyncInterner<S> {
+        fn from(interner: Interner<S>) -> Self {
+            Self {
+                inner: RwLock::new(interner),
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! A map for read-mostly data, with wait-free readers and a single writer.
+//!
+//! Keeps two copies of a `HashMap`. Readers read one, the writer changes the other and logs its
+//! changes. `WriteHandle::publish` swaps the copies, waits until no reader is left in the old one,
+//! and replays the log on it. Readers never wait for the writer, they only bump a counter when
+//! they start and stop reading. The cost is twice the memory and `K: Clone, V: Clone`.
+//!
+//! Every thread reads through its own `ReadHandle`, which are made by cloning.
+//!
+//! Tested with loom: `RUSTFLAGS="--cfg loom" cargo test --release left_right`.
+
+use core::{
+    cell::Cell,
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    mem::ManuallyDrop,
+    ops::Deref,
+};
+use std::{sync::PoisonError, vec::Vec};
+
+#[cfg(loom)]
+use loom::{
+    cell::{ConstPtr, UnsafeCell},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::yield_now,
+};
+#[cfg(not(loom))]
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::yield_now,
+};
+
+#[cfg(not(loom))]
+use self::cell::{ConstPtr, UnsafeCell};
+use crate::hash_map::{DefaultHashBuilder, HashMap};
+
+/// `core::cell::UnsafeCell` with the API of loom's, which tracks accesses.
+#[cfg(not(loom))]
+mod cell {
+    pub(super) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+    impl<T> UnsafeCell<T> {
+        pub(super) fn new(data: T) -> Self {
+            Self(core::cell::UnsafeCell::new(data))
+        }
+
+        pub(super) fn get(&self) -> ConstPtr<T> {
+            ConstPtr(self.0.get())
+        }
+
+        pub(super) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+            f(self.0.get())
+        }
+    }
+
+    pub(super) struct ConstPtr<T>(*const T);
+
+    impl<T> ConstPtr<T> {
+        pub(super) unsafe fn deref(&self) -> &T {
+            unsafe { &*self.0 }
+        }
+    }
+}
+
+enum Op<K, V> {
+    Insert(K, V),
+    Remove(K),
+    Clear,
+}
+
+struct Inner<K, V, S> {
+    maps: [UnsafeCell<HashMap<K, V, S>>; 2],
+    /// Index of the copy that new readers read.
+    read_idx: AtomicUsize,
+    /// One per `ReadHandle`, odd while the handle is reading.
+    epochs: Mutex<Vec<Arc<AtomicUsize>>>,
+}
+
+// Safety: Readers on many threads share the read copy, the writer changes the other copy from its
+// own thread. They never access the same copy at the same time.
+unsafe impl<K: Send, V: Send, S: Send> Send for Inner<K, V, S> {}
+unsafe impl<K: Send + Sync, V: Send + Sync, S: Send + Sync> Sync for Inner<K, V, S> {}
+
+/// Makes an empty map, returns its only writer and a first reader.
+pub fn new<K, V>() -> (
+    WriteHandle<K, V, DefaultHashBuilder>,
+    ReadHandle<K, V, DefaultHashBuilder>,
+) {
+    with_hasher(DefaultHashBuilder::default())
+}
+
+pub fn with_hasher<K, V, S: Clone>(hash_builder: S) -> (WriteHandle<K, V, S>, ReadHandle<K, V, S>) {
+    let inner = Arc::new(Inner {
+        maps: [
+            UnsafeCell::new(HashMap::with_hasher(hash_builder.clone())),
+            UnsafeCell::new(HashMap::with_hasher(hash_builder)),
+        ],
+        read_idx: AtomicUsize::new(0),
+        epochs: Mutex::new(Vec::new()),
+    });
+    let reader = ReadHandle::new(Arc::clone(&inner));
+    let writer = WriteHandle {
+        inner,
+        log: Vec::new(),
+    };
+    (writer, reader)
+}
+
+/// Changes the map. Changes are visible to readers after `publish`.
+pub struct WriteHandle<K, V, S = DefaultHashBuilder> {
+    inner: Arc<Inner<K, V, S>>,
+    /// Changes made to the write copy since the last `publish`, to be replayed on the other copy.
+    log: Vec<Op<K, V>>,
+}
+
+impl<K, V, S> Debug for WriteHandle<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriteHandle")
+            .field("pending", &self.log.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V, S> WriteHandle<K, V, S> {
+    /// A new reader, e.g. for another thread.
+    pub fn reader(&self) -> ReadHandle<K, V, S> {
+        ReadHandle::new(Arc::clone(&self.inner))
+    }
+
+    /// Number of changes not yet visible to readers.
+    pub fn pending(&self) -> usize {
+        self.log.len()
+    }
+
+    /// Calls `f` with the copy that readers don't read.
+    fn with_write_copy<R>(&mut self, f: impl FnOnce(&mut HashMap<K, V, S>) -> R) -> R {
+        let idx = 1 - self.inner.read_idx.load(Ordering::Relaxed);
+        // Safety: Readers only enter the copy at `read_idx`, and `publish` waited for the ones
+        // still in this copy. `&mut self` makes the writer's access unique.
+        self.inner.maps[idx].with_mut(|map| f(unsafe { &mut *map }))
+    }
+
+    /// Waits until every reader that might have seen the old `read_idx` has stopped reading.
+    fn wait_for_readers(&self) {
+        let epochs = self
+            .inner
+            .epochs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        // A read-modify-write reads the latest value. If the reader's increment comes after it, it
+        // synchronizes with it and the reader sees the new `read_idx`. This avoids relying on
+        // `SeqCst`, which loom doesn't model fully.
+        let seen: Vec<usize> = epochs
+            .iter()
+            .map(|epoch| epoch.fetch_add(0, Ordering::AcqRel))
+            .collect();
+        for (epoch, seen) in epochs.iter().zip(seen) {
+            // Even means not reading.
+            if seen % 2 == 0 {
+                continue;
+            }
+            while epoch.load(Ordering::Acquire) == seen {
+                yield_now();
+            }
+        }
+    }
+}
+
+impl<K, V, S> WriteHandle<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) {
+        self.with_write_copy(|map| map.insert(key.clone(), value.clone()));
+        self.log.push(Op::Insert(key, value));
+    }
+
+    pub fn remove(&mut self, key: K) {
+        self.with_write_copy(|map| map.remove(&key));
+        self.log.push(Op::Remove(key));
+    }
+
+    pub fn clear(&mut self) {
+        self.with_write_copy(HashMap::clear);
+        self.log.push(Op::Clear);
+    }
+
+    /// Makes the changes so far visible to readers. Waits for readers that are still reading the
+    /// old copy.
+    pub fn publish(&mut self) {
+        if self.log.is_empty() {
+            return;
+        }
+        let read_idx = self.inner.read_idx.load(Ordering::Relaxed);
+        self.inner.read_idx.store(1 - read_idx, Ordering::Release);
+        self.wait_for_readers();
+        let mut log = core::mem::take(&mut self.log);
+        self.with_write_copy(|map| {
+            for op in log.drain(..) {
+                match op {
+                    Op::Insert(k, v) => {
+                        map.insert(k, v);
+                    }
+                    Op::Remove(k) => {
+                        map.remove(&k);
+                    }
+                    Op::Clear => map.clear(),
+                }
+            }
+        });
+        self.log = log;
+    }
+}
+
+/// Reads the map. Not `Sync`, every thread needs its own, made with `Clone` or
+/// `WriteHandle::reader`.
+pub struct ReadHandle<K, V, S = DefaultHashBuilder> {
+    inner: Arc<Inner<K, V, S>>,
+    epoch: Arc<AtomicUsize>,
+    /// Number of live `ReadGuard`s, they may be nested.
+    depth: Cell<usize>,
+    /// The copy the outermost `ReadGuard` entered.
+    idx: Cell<usize>,
+}
+
+impl<K, V, S> ReadHandle<K, V, S> {
+    fn new(inner: Arc<Inner<K, V, S>>) -> Self {
+        let epoch = Arc::new(AtomicUsize::new(0));
+        inner
+            .epochs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::clone(&epoch));
+        Self {
+            inner,
+            epoch,
+            depth: Cell::new(0),
+            idx: Cell::new(0),
+        }
+    }
+
+    /// Wait-free. The map doesn't change while the guard is alive, but holding it for long delays
+    /// the writer's next `publish`.
+    pub fn enter(&self) -> ReadGuard<'_, K, V, S> {
+        if self.depth.get() == 0 {
+            self.epoch.fetch_add(1, Ordering::AcqRel);
+            self.idx.set(self.inner.read_idx.load(Ordering::Acquire));
+        }
+        self.depth.set(self.depth.get() + 1);
+        ReadGuard {
+            handle: self,
+            map: ManuallyDrop::new(self.inner.maps[self.idx.get()].get()),
+        }
+    }
+}
+
+impl<K, V, S> Clone for ReadHandle<K, V, S> {
+    fn clone(&self) -> Self {
+        Self::new(Arc::clone(&self.inner))
+    }
+}
+
+impl<K, V, S> Drop for ReadHandle<K, V, S> {
+    fn drop(&mut self) {
+        self.inner
+            .epochs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|epoch| !Arc::ptr_eq(epoch, &self.epoch));
+    }
+}
+
+impl<K, V, S> Debug for ReadHandle<K, V, S>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ReadHandle").field(&*self.enter()).finish()
+    }
+}
+
+/// A consistent view of the map, see `ReadHandle::enter`.
+pub struct ReadGuard<'a, K, V, S> {
+    handle: &'a ReadHandle<K, V, S>,
+    /// Dropped before leaving, so that loom sees the access end before the writer's next one.
+    map: ManuallyDrop<ConstPtr<HashMap<K, V, S>>>,
+}
+
+impl<K, V, S> Deref for ReadGuard<'_, K, V, S> {
+    type Target = HashMap<K, V, S>;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: The writer doesn't touch the copy this handle entered until the handle's epoch
+        // changes, which happens after the last guard is dropped.
+        unsafe { ConstPtr::deref(&self.map) }
+    }
+}
+
+impl<K, V, S> Drop for ReadGuard<'_, K, V, S> {
+    fn drop(&mut self) {
+        // Safety: Not used after this.
+        unsafe { ManuallyDrop::drop(&mut self.map) };
+        let depth = self.handle.depth.get() - 1;
+        self.handle.depth.set(depth);
+        if depth == 0 {
+            self.handle.epoch.fetch_add(1, Ordering::Release);
+        }
+    }
+}
@@ -1,3 +1,41 @@
+//! Without the default `std` feature the crate is `no_std` and only needs `alloc`. There is no
+//! default hasher then, maps have to be created with an explicit `BuildHasher`, e.g. one from
+//! `hashers`.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub use allocator_api2::alloc::{AllocError, Allocator, Global};
+
+pub mod any_map;
+pub mod array_map;
+pub mod bi_map;
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod concurrent_map;
+#[cfg(feature = "std")]
+pub mod expiring_map;
+pub mod frozen_map;
+pub mod hash_bag;
 pub mod hash_map;
 pub mod hash_set;
+pub mod hashers;
+pub mod index_map;
+pub mod index_set;
+pub mod int_map;
+pub mod interner;
+#[cfg(feature = "std")]
+pub mod left_right;
+pub mod multi_map;
+pub mod persistent_map;
+mod raw_index;
+#[cfg(feature = "rayon")]
+pub mod rayon;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod small_map;
 mod tests;
+pub mod weak_value_map;
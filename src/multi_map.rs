@@ -0,0 +1,256 @@
+//! A map with any number of values per key.
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    slice,
+};
+
+use crate::hash_map::{self, Bucket, BucketIter, DefaultHashBuilder, HashMap, Slot};
+
+/// Keeps every value as its own entry in the bucket chains of a `HashMap`, instead of a `Vec` per
+/// key. Values with the same key are kept in the order they were inserted.
+#[derive(Clone)]
+pub struct MultiMap<K, V, S = DefaultHashBuilder> {
+    map: HashMap<K, V, S>,
+}
+
+impl<K, V, S> Debug for MultiMap<K, V, S>
+where
+    K: Debug + Eq,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter_grouped()
+                    .map(|(k, values)| (k, values.collect::<Vec<_>>())),
+            )
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> MultiMap<K, V, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    /// `capacity` is in values, not keys.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(capacity),
+        }
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hash_builder),
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_capacity_and_hasher(capacity, hash_builder),
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Number of values, over all keys.
+    pub fn total_len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Every key-value pair, a key comes up once per value.
+    pub fn iter(&self) -> hash_map::Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    /// Every key once, with its values. Every value is compared with the distinct keys before it
+    /// in its bucket, so this gets slow if many distinct keys collide.
+    pub fn iter_grouped(&self) -> Groups<'_, K, V> {
+        let (buckets, slots) = self.map.parts();
+        Groups {
+            buckets: buckets.iter(),
+            slots,
+            entries: BucketIter::empty(),
+            seen: Vec::new(),
+        }
+    }
+}
+
+impl<K, V, S> Default for MultiMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self {
+            map: HashMap::default(),
+        }
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Adds a value after the ones the key already has.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.map.insert_duplicate(key, value);
+    }
+
+    /// The first value of the key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    /// The values of the key, in insertion order.
+    pub fn get_all<'a>(&'a self, key: &'a K) -> GetAll<'a, K, V> {
+        GetAll {
+            key,
+            entries: self.map.bucket_of(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Number of values of the key.
+    pub fn count(&self, key: &K) -> usize {
+        self.get_all(key).count()
+    }
+
+    /// Removes the first value of the key.
+    pub fn remove_one(&mut self, key: &K) -> Option<V> {
+        self.map.remove(key)
+    }
+
+    /// Removes every value of the key, returns them in insertion order.
+    pub fn remove_all(&mut self, key: &K) -> Vec<V> {
+        let mut values = Vec::new();
+        while let Some(value) = self.map.remove(key) {
+            values.push(value);
+        }
+        values
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for MultiMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a MultiMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = hash_map::Iter<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V, S> IntoIterator for MultiMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = hash_map::IntoIter<K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+/// The values of one key, see `MultiMap::get_all`.
+pub struct GetAll<'a, K, V> {
+    key: &'a K,
+    /// The bucket of the key, which may also hold other keys.
+    entries: BucketIter<'a, K, V>,
+}
+
+impl<K, V> Clone for GetAll<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key,
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<'a, K: Eq, V> Iterator for GetAll<'a, K, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.key;
+        self.entries.find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+}
+
+/// Iterator over the keys of a `MultiMap` with their values, see `MultiMap::iter_grouped`.
+pub struct Groups<'a, K, V> {
+    buckets: slice::Iter<'a, Bucket<K, V>>,
+    slots: &'a [Slot<K, V>],
+    /// The rest of the current bucket.
+    entries: BucketIter<'a, K, V>,
+    /// The keys of the current bucket that were already yielded.
+    seen: Vec<&'a K>,
+}
+
+impl<'a, K: Eq, V> Iterator for Groups<'a, K, V> {
+    type Item = (&'a K, GetAll<'a, K, V>);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let rest = self.entries.clone();
+            let Some((k, _)) = self.entries.next() else {
+                self.entries = self.buckets.next()?.iter(self.slots);
+                self.seen.clear();
+                continue;
+            };
+            // The first entry of the key in the bucket starts a group, the entries before it
+            // have other keys.
+            if !self.seen.contains(&k) {
+                self.seen.push(k);
+                let get_all = GetAll {
+                    key: k,
+                    entries: rest,
+                };
+                return Some((k, get_all));
+            }
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! An immutable map whose versions share structure.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    slice,
+};
+
+use crate::hash_map::DefaultHashBuilder;
+
+/// Bits of the hash used per level of the trie.
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+/// A hash array mapped trie. Every node has up to 32 children, picked by 5 bits of the hash, and
+/// only stores the children that exist.
+///
+/// Nodes are shared between versions with `Arc`, so cloning the map is O(1), and `insert` and
+/// `remove` only copy the O(log n) nodes on the path to the key. Keys and values are never
+/// copied, they don't need to be `Clone`.
+pub struct PersistentHashMap<K, V, S = DefaultHashBuilder> {
+    root: Option<Arc<Node<K, V>>>,
+    len: usize,
+    hash_builder: S,
+}
+
+enum Node<K, V> {
+    Leaf {
+        hash: u64,
+        key: K,
+        value: V,
+    },
+    /// Leaves whose keys have the same hash.
+    Collision {
+        hash: u64,
+        leaves: Vec<Arc<Node<K, V>>>,
+    },
+    Branch {
+        /// Which of the 32 children exist.
+        bitmap: u32,
+        children: Vec<Arc<Node<K, V>>>,
+    },
+}
+
+/// A difference between two versions of a map, see `PersistentHashMap::diff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffItem<'a, K, V> {
+    Added(&'a K, &'a V),
+    Removed(&'a K, &'a V),
+    Changed { key: &'a K, old: &'a V, new: &'a V },
+}
+
+impl<K, V, S: Clone> Clone for PersistentHashMap<K, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            len: self.len,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+}
+
+impl<K, V, S> Debug for PersistentHashMap<K, V, S>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+/// The 5 bits of the hash for the level at `shift`.
+fn fragment(hash: u64, shift: u32) -> u32 {
+    ((hash >> shift) & MASK) as u32
+}
+
+impl<K, V> Node<K, V> {
+    /// Position in `children` of the child for `fragment`.
+    fn position(bitmap: u32, fragment: u32) -> usize {
+        (bitmap & ((1 << fragment) - 1)).count_ones() as usize
+    }
+
+    /// A branch holding two nodes with different hashes.
+    fn pair(shift: u32, lhs: Arc<Self>, lhs_hash: u64, rhs: Arc<Self>, rhs_hash: u64) -> Arc<Self> {
+        let (lhs_fragment, rhs_fragment) = (fragment(lhs_hash, shift), fragment(rhs_hash, shift));
+        let node = if lhs_fragment == rhs_fragment {
+            Node::Branch {
+                bitmap: 1 << lhs_fragment,
+                children: vec![Self::pair(shift + BITS, lhs, lhs_hash, rhs, rhs_hash)],
+            }
+        } else {
+            Node::Branch {
+                bitmap: (1 << lhs_fragment) | (1 << rhs_fragment),
+                children: if lhs_fragment < rhs_fragment {
+                    vec![lhs, rhs]
+                } else {
+                    vec![rhs, lhs]
+                },
+            }
+        };
+        Arc::new(node)
+    }
+
+    fn is_branch(&self) -> bool {
+        matches!(self, Node::Branch { .. })
+    }
+}
+
+impl<K: Eq, V> Node<K, V> {
+    fn get(&self, hash: u64, shift: u32, k: &K) -> Option<(&K, &V)> {
+        match self {
+            Node::Leaf { key, value, .. } => (key == k).then_some((key, value)),
+            Node::Collision { leaves, .. } => {
+                leaves.iter().find_map(|leaf| leaf.get(hash, shift, k))
+            }
+            Node::Branch { bitmap, children } => {
+                let fragment = fragment(hash, shift);
+                if bitmap & (1 << fragment) == 0 {
+                    return None;
+                }
+                children[Self::position(*bitmap, fragment)].get(hash, shift + BITS, k)
+            }
+        }
+    }
+
+    /// Returns the new node, and if the key wasn't there before.
+    fn insert(self: &Arc<Self>, hash: u64, shift: u32, k: K, v: V) -> (Arc<Self>, bool) {
+        let leaf = |k, v| {
+            Arc::new(Node::Leaf {
+                hash,
+                key: k,
+                value: v,
+            })
+        };
+        match &**self {
+            Node::Leaf { hash: h, key, .. } if *h == hash => {
+                if *key == k {
+                    (leaf(k, v), false)
+                } else {
+                    let leaves = vec![Arc::clone(self), leaf(k, v)];
+                    (Arc::new(Node::Collision { hash, leaves }), true)
+                }
+            }
+            Node::Collision { hash: h, leaves } if *h == hash => {
+                let mut leaves = leaves.clone();
+                let existing = leaves
+                    .iter()
+                    .position(|leaf| matches!(&**leaf, Node::Leaf { key, .. } if *key == k));
+                let added = existing.is_none();
+                match existing {
+                    Some(i) => leaves[i] = leaf(k, v),
+                    None => leaves.push(leaf(k, v)),
+                }
+                (Arc::new(Node::Collision { hash, leaves }), added)
+            }
+            Node::Leaf { hash: h, .. } | Node::Collision { hash: h, .. } => {
+                let node = Self::pair(shift, Arc::clone(self), *h, leaf(k, v), hash);
+                (node, true)
+            }
+            Node::Branch { bitmap, children } => {
+                let fragment = fragment(hash, shift);
+                let pos = Self::position(*bitmap, fragment);
+                let mut children = children.clone();
+                let added = if bitmap & (1 << fragment) == 0 {
+                    children.insert(pos, leaf(k, v));
+                    true
+                } else {
+                    let (child, added) = children[pos].insert(hash, shift + BITS, k, v);
+                    children[pos] = child;
+                    added
+                };
+                let bitmap = bitmap | (1 << fragment);
+                (Arc::new(Node::Branch { bitmap, children }), added)
+            }
+        }
+    }
+
+    /// Returns `None` if the key isn't there, otherwise the new node, which is `None` if empty.
+    fn remove(&self, hash: u64, shift: u32, k: &K) -> Option<Option<Arc<Self>>> {
+        match self {
+            Node::Leaf { key, .. } => (key == k).then_some(None),
+            Node::Collision { hash, leaves } => {
+                let i = leaves
+                    .iter()
+                    .position(|leaf| matches!(&**leaf, Node::Leaf { key, .. } if key == k))?;
+                let mut leaves = leaves.clone();
+                leaves.remove(i);
+                if leaves.len() == 1 {
+                    return Some(leaves.pop());
+                }
+                let hash = *hash;
+                Some(Some(Arc::new(Node::Collision { hash, leaves })))
+            }
+            Node::Branch { bitmap, children } => {
+                let fragment = fragment(hash, shift);
+                if bitmap & (1 << fragment) == 0 {
+                    return None;
+                }
+                let pos = Self::position(*bitmap, fragment);
+                let child = children[pos].remove(hash, shift + BITS, k)?;
+                let mut children = children.clone();
+                let mut bitmap = *bitmap;
+                match child {
+                    Some(child) => children[pos] = child,
+                    None => {
+                        children.remove(pos);
+                        bitmap &= !(1 << fragment);
+                    }
+                }
+                // A lone leaf can move up, its position only depends on a prefix of its hash.
+                match children.as_slice() {
+                    [] => Some(None),
+                    [child] if !child.is_branch() => Some(Some(Arc::clone(child))),
+                    _ => Some(Some(Arc::new(Node::Branch { bitmap, children }))),
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, S> PersistentHashMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            hash_builder,
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.into_iter()
+    }
+
+    /// If `other` is the same version as `self` or was cloned from it without changes.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(lhs), Some(rhs)) => Arc::ptr_eq(lhs, rhs),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> PersistentHashMap<K, V, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, S> Default for PersistentHashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    pub fn get_kv<'a>(&'a self, key: &K) -> Option<(&'a K, &'a V)> {
+        let hash = self.hash_builder.hash_one(key);
+        self.root.as_ref()?.get(hash, 0, key)
+    }
+
+    pub fn get<'a>(&'a self, key: &K) -> Option<&'a V> {
+        self.get_kv(key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_kv(key).is_some()
+    }
+
+    /// A new version with `key` set to `value`. `self` is unchanged.
+    #[must_use]
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let hash = self.hash_builder.hash_one(&key);
+        let (root, added) = match &self.root {
+            Some(root) => root.insert(hash, 0, key, value),
+            None => (Arc::new(Node::Leaf { hash, key, value }), true),
+        };
+        Self {
+            root: Some(root),
+            len: self.len + added as usize,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+
+    /// A new version without `key`. `self` is unchanged.
+    #[must_use]
+    pub fn remove(&self, key: &K) -> Self {
+        let hash = self.hash_builder.hash_one(key);
+        match self
+            .root
+            .as_ref()
+            .and_then(|root| root.remove(hash, 0, key))
+        {
+            Some(root) => Self {
+                root,
+                len: self.len - 1,
+                hash_builder: self.hash_builder.clone(),
+            },
+            None => self.clone(),
+        }
+    }
+}
+
+impl<K, V, S> PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+{
+    /// The changes from `self` to `other`. Subtrees shared by both versions are skipped, so
+    /// comparing a version to one derived from it takes time proportional to the changes.
+    ///
+    /// Both maps must use the same hasher.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Vec<DiffItem<'a, K, V>> {
+        let mut diff = Vec::new();
+        diff_nodes(self.root.as_ref(), other.root.as_ref(), 0, &mut diff);
+        diff
+    }
+}
+
+fn diff_nodes<'a, K: Eq, V: PartialEq>(
+    old: Option<&'a Arc<Node<K, V>>>,
+    new: Option<&'a Arc<Node<K, V>>>,
+    shift: u32,
+    diff: &mut Vec<DiffItem<'a, K, V>>,
+) {
+    if let (Some(old), Some(new)) = (old, new) {
+        if Arc::ptr_eq(old, new) {
+            return;
+        }
+    }
+    if let (Some(old), Some(new)) = (old, new) {
+        if let (
+            Node::Branch {
+                bitmap: old_bitmap,
+                children: old_children,
+            },
+            Node::Branch {
+                bitmap: new_bitmap,
+                children: new_children,
+            },
+        ) = (&**old, &**new)
+        {
+            let child = |bitmap: u32, children: &'a [Arc<Node<K, V>>], fragment: u32| {
+                (bitmap & (1 << fragment) != 0)
+                    .then(|| &children[Node::<K, V>::position(bitmap, fragment)])
+            };
+            for fragment in 0..(1 << BITS) {
+                diff_nodes(
+                    child(*old_bitmap, old_children, fragment),
+                    child(*new_bitmap, new_children, fragment),
+                    shift + BITS,
+                    diff,
+                );
+            }
+            return;
+        }
+    }
+    // Different kinds of nodes, at least one of them is a leaf or a collision, so small.
+    for (hash, key, value) in old.into_iter().flat_map(|node| Leaves::new(node)) {
+        match new.and_then(|new| new.get(hash, shift, key)) {
+            None => diff.push(DiffItem::Removed(key, value)),
+            Some((_, new_value)) if new_value != value => diff.push(DiffItem::Changed {
+                key,
+                old: value,
+                new: new_value,
+            }),
+            Some(_) => (),
+        }
+    }
+    for (hash, key, value) in new.into_iter().flat_map(|node| Leaves::new(node)) {
+        if old.and_then(|old| old.get(hash, shift, key)).is_none() {
+            diff.push(DiffItem::Added(key, value));
+        }
+    }
+}
+
+/// Depth-first walk over the leaves of a subtree.
+struct Leaves<'a, K, V> {
+    stack: Vec<slice::Iter<'a, Arc<Node<K, V>>>>,
+}
+
+impl<'a, K, V> Leaves<'a, K, V> {
+    fn new(root: &'a Arc<Node<K, V>>) -> Self {
+        Self {
+            stack: vec![slice::from_ref(root).iter()],
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Leaves<'a, K, V> {
+    type Item = (u64, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(node) = self.stack.last_mut()?.next() else {
+                self.stack.pop();
+                continue;
+            };
+            match &**node {
+                Node::Leaf { hash, key, value } => return Some((*hash, key, value)),
+                Node::Collision { leaves, .. } => self.stack.push(leaves.iter()),
+                Node::Branch { children, .. } => self.stack.push(children.iter()),
+            }
+        }
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    leaves: Option<Leaves<'a, K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, k, v) = self.leaves.as_mut()?.next()?;
+        Some((k, v))
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a PersistentHashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Iter {
+            leaves: self.root.as_ref().map(Leaves::new),
+        }
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for PersistentHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        iter.into_iter()
+            .fold(Self::default(), |map, (k, v)| map.insert(k, v))
+    }
+}
@@ -0,0 +1,174 @@
+//! A hash table of positions into a separate `Vec` of entries, for maps that keep their entries
+//! dense (`IndexMap`). The table only knows hashes and positions, comparing keys is left to the
+//! caller, so the entries can be laid out however the map wants.
+
+use alloc::{vec, vec::Vec};
+
+const EMPTY: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    hash: u64,
+    /// Position of the entry, `EMPTY` if the slot is free.
+    idx: usize,
+}
+
+impl Slot {
+    const EMPTY: Self = Self {
+        hash: 0,
+        idx: EMPTY,
+    };
+
+    fn is_empty(&self) -> bool {
+        self.idx == EMPTY
+    }
+}
+
+/// Open addressing with linear probing, at most 3/4 full. Removal shifts the following slots back
+/// instead of leaving tombstones.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RawIndex {
+    /// Empty, or a power of two long.
+    slots: Vec<Slot>,
+    len: usize,
+}
+
+impl RawIndex {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let mut index = Self::new();
+        index.reserve(capacity);
+        index
+    }
+
+    fn mask(&self) -> usize {
+        self.slots.len().wrapping_sub(1)
+    }
+
+    /// Position of the first entry with `hash` for which `eq` returns true.
+    pub(crate) fn find(&self, hash: u64, mut eq: impl FnMut(usize) -> bool) -> Option<usize> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let mask = self.mask();
+        let mut i = hash as usize & mask;
+        loop {
+            let slot = self.slots[i];
+            if slot.is_empty() {
+                return None;
+            }
+            if slot.hash == hash && eq(slot.idx) {
+                return Some(slot.idx);
+            }
+            i = (i + 1) & mask;
+        }
+    }
+
+    /// Adds the entry at `idx`, which must not be in the table yet.
+    pub(crate) fn insert(&mut self, hash: u64, idx: usize) {
+        self.reserve(1);
+        self.insert_unchecked(hash, idx);
+    }
+
+    /// Like `insert`, but there must be room for it.
+    fn insert_unchecked(&mut self, hash: u64, idx: usize) {
+        let mask = self.mask();
+        let mut i = hash as usize & mask;
+        while !self.slots[i].is_empty() {
+            i = (i + 1) & mask;
+        }
+        self.slots[i] = Slot { hash, idx };
+        self.len += 1;
+    }
+
+    fn slot_of(&self, hash: u64, idx: usize) -> usize {
+        let mask = self.mask();
+        let mut i = hash as usize & mask;
+        loop {
+            let slot = self.slots[i];
+            assert!(!slot.is_empty(), "entry not in the index");
+            if slot.idx == idx {
+                return i;
+            }
+            i = (i + 1) & mask;
+        }
+    }
+
+    /// Removes the entry at `idx`, which must be in the table with `hash`.
+    pub(crate) fn remove(&mut self, hash: u64, idx: usize) {
+        let mask = self.mask();
+        let mut hole = self.slot_of(hash, idx);
+        let mut i = (hole + 1) & mask;
+        // Moves back every following slot whose probe sequence passes the hole.
+        while !self.slots[i].is_empty() {
+            let ideal = self.slots[i].hash as usize & mask;
+            if i.wrapping_sub(ideal) & mask >= i.wrapping_sub(hole) & mask {
+                self.slots[hole] = self.slots[i];
+                hole = i;
+            }
+            i = (i + 1) & mask;
+        }
+        self.slots[hole] = Slot::EMPTY;
+        self.len -= 1;
+    }
+
+    /// Points the entry at `old` to `new`, e.g. after the entry was moved.
+    pub(crate) fn replace(&mut self, hash: u64, old: usize, new: usize) {
+        let i = self.slot_of(hash, old);
+        self.slots[i].idx = new;
+    }
+
+    /// Changes every position with `f`, for moves of many entries at once.
+    pub(crate) fn update_all(&mut self, mut f: impl FnMut(usize) -> usize) {
+        for slot in &mut self.slots {
+            if !slot.is_empty() {
+                slot.idx = f(slot.idx);
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.slots.fill(Slot::EMPTY);
+        self.len = 0;
+    }
+
+    /// Replaces the contents with entries `0..` having the given hashes.
+    pub(crate) fn rebuild(&mut self, hashes: impl IntoIterator<Item = u64>) {
+        self.clear();
+        for (idx, hash) in hashes.into_iter().enumerate() {
+            self.insert(hash, idx);
+        }
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let needed = self.len + additional;
+        if needed * 4 > self.slots.len() * 3 {
+            self.resize(needed);
+        }
+    }
+
+    pub(crate) fn shrink_to(&mut self, min_capacity: usize) {
+        let needed = self.len.max(min_capacity);
+        if needed == 0 {
+            self.slots = Vec::new();
+        } else if slots_for(needed) < self.slots.len() {
+            self.resize(needed);
+        }
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        let old = core::mem::replace(&mut self.slots, vec![Slot::EMPTY; slots_for(capacity)]);
+        self.len = 0;
+        for slot in old.into_iter().filter(|slot| !slot.is_empty()) {
+            self.insert_unchecked(slot.hash, slot.idx);
+        }
+    }
+}
+
+/// Number of slots to hold `capacity` entries.
+fn slots_for(capacity: usize) -> usize {
+    (capacity * 4 / 3 + 1).next_power_of_two().max(8)
+}
@@ -0,0 +1,364 @@
+//! Parallel iterators for `HashMap` and `HashSet`, behind the `rayon` feature.
+//!
+//! Like the sequential iterators, they go over the first entry of every bucket, then the slots of
+//! the overflow arena. Both are slices, so rayon splits the work on bucket (and slot) ranges.
+//!
+//! `par_iter`, `par_iter_mut` and `into_par_iter` come from rayon's traits, `par_drain` from
+//! `ParallelDrainFull` and `par_extend` from `ParallelExtend`.
+
+use core::hash::{BuildHasher, Hash};
+
+use ::rayon::iter::{
+    plumbing::UnindexedConsumer, IntoParallelIterator, ParallelDrainFull, ParallelExtend,
+    ParallelIterator,
+};
+use allocator_api2::alloc::Allocator;
+use std::{collections::LinkedList, vec::Vec};
+
+use crate::{hash_map::HashMap, hash_set::HashSet};
+
+/// Parallel iterator over the entries of a `HashMap`.
+pub struct ParIter<'a, K, V, S, A: Allocator> {
+    map: &'a HashMap<K, V, S, A>,
+}
+
+impl<'a, K, V, S, A> ParallelIterator for ParIter<'a, K, V, S, A>
+where
+    K: Sync,
+    V: Sync,
+    S: Sync,
+    A: Allocator + Sync,
+{
+    type Item = (&'a K, &'a V);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let (buckets, slots) = self.map.parts();
+        buckets
+            .into_par_iter()
+            .filter_map(|bucket| bucket.first())
+            .chain(slots.into_par_iter().filter_map(|slot| slot.entry()))
+            .drive_unindexed(consumer)
+    }
+}
+
+/// Parallel iterator over the entries of a `HashMap`, by mutable reference.
+pub struct ParIterMut<'a, K, V, S, A: Allocator> {
+    map: &'a mut HashMap<K, V, S, A>,
+}
+
+impl<'a, K, V, S, A> ParallelIterator for ParIterMut<'a, K, V, S, A>
+where
+    K: Send,
+    V: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Item = (&'a mut K, &'a mut V);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let (buckets, slots) = self.map.parts_mut();
+        buckets
+            .into_par_iter()
+            .filter_map(|bucket| bucket.first_mut())
+            .chain(slots.into_par_iter().filter_map(|slot| slot.entry_mut()))
+            .drive_unindexed(consumer)
+    }
+}
+
+/// Parallel iterator that removes all entries of a `HashMap`.
+///
+/// The map is left empty with its capacity, even if the iterator is dropped before it's driven
+/// or the consumer stops early.
+pub struct ParDrain<'a, K, V, S, A: Allocator> {
+    map: &'a mut HashMap<K, V, S, A>,
+}
+
+impl<K, V, S, A: Allocator> Drop for ParDrain<'_, K, V, S, A> {
+    fn drop(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl<K, V, S, A> ParallelIterator for ParDrain<'_, K, V, S, A>
+where
+    K: Send,
+    V: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Item = (K, V);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        // Leaves the buckets pointing at vacant slots, which `Drop` clears up.
+        let (buckets, slots) = self.map.parts_mut();
+        buckets
+            .into_par_iter()
+            .filter_map(|bucket| bucket.take_first())
+            .chain(slots.into_par_iter().filter_map(|slot| slot.take_entry()))
+            .drive_unindexed(consumer)
+    }
+}
+
+/// Parallel iterator over the entries of a `HashMap`, by value.
+pub struct IntoParIter<K, V, S, A: Allocator> {
+    map: HashMap<K, V, S, A>,
+}
+
+impl<K, V, S, A> ParallelIterator for IntoParIter<K, V, S, A>
+where
+    K: Send,
+    V: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Item = (K, V);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(mut self, consumer: C) -> C::Result {
+        ParDrain { map: &mut self.map }.drive_unindexed(consumer)
+    }
+}
+
+impl<'a, K, V, S, A> IntoParallelIterator for &'a HashMap<K, V, S, A>
+where
+    K: Sync,
+    V: Sync,
+    S: Sync,
+    A: Allocator + Sync,
+{
+    type Iter = ParIter<'a, K, V, S, A>;
+    type Item = (&'a K, &'a V);
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter { map: self }
+    }
+}
+
+impl<'a, K, V, S, A> IntoParallelIterator for &'a mut HashMap<K, V, S, A>
+where
+    K: Send,
+    V: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Iter = ParIterMut<'a, K, V, S, A>;
+    type Item = (&'a mut K, &'a mut V);
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIterMut { map: self }
+    }
+}
+
+impl<K, V, S, A> IntoParallelIterator for HashMap<K, V, S, A>
+where
+    K: Send,
+    V: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Iter = IntoParIter<K, V, S, A>;
+    type Item = (K, V);
+
+    fn into_par_iter(self) -> Self::Iter {
+        IntoParIter { map: self }
+    }
+}
+
+impl<'a, K, V, S, A> ParallelDrainFull for &'a mut HashMap<K, V, S, A>
+where
+    K: Send,
+    V: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Iter = ParDrain<'a, K, V, S, A>;
+    type Item = (K, V);
+
+    fn par_drain(self) -> Self::Iter {
+        ParDrain { map: self }
+    }
+}
+
+/// Collects the items in parallel, the inserting is sequential.
+fn collect<I: IntoParallelIterator>(par_iter: I) -> (LinkedList<Vec<I::Item>>, usize) {
+    let list: LinkedList<Vec<I::Item>> = par_iter
+        .into_par_iter()
+        .fold(Vec::new, |mut vec, item| {
+            vec.push(item);
+            vec
+        })
+        .map(|vec| LinkedList::from([vec]))
+        .reduce(LinkedList::new, |mut lhs, mut rhs| {
+            lhs.append(&mut rhs);
+            lhs
+        });
+    let len = list.iter().map(Vec::len).sum();
+    (list, len)
+}
+
+impl<K, V, S, A> ParallelExtend<(K, V)> for HashMap<K, V, S, A>
+where
+    K: Hash + Eq + Send,
+    V: Send,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn par_extend<I: IntoParallelIterator<Item = (K, V)>>(&mut self, par_iter: I) {
+        let (list, len) = collect(par_iter);
+        self.reserve(len);
+        for (k, v) in list.into_iter().flatten() {
+            self.insert(k, v);
+        }
+    }
+}
+
+/// Parallel iterator over the elements of a `HashSet`.
+pub struct SetParIter<'a, T, S, A: Allocator> {
+    inner: ParIter<'a, T, (), S, A>,
+}
+
+impl<'a, T, S, A> ParallelIterator for SetParIter<'a, T, S, A>
+where
+    T: Sync,
+    S: Sync,
+    A: Allocator + Sync,
+{
+    type Item = &'a T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        self.inner.map(|(k, ())| k).drive_unindexed(consumer)
+    }
+}
+
+/// Parallel iterator over the elements of a `HashSet`, by mutable reference, see
+/// `HashSet::iter_mut`.
+pub struct SetParIterMut<'a, T, S, A: Allocator> {
+    inner: ParIterMut<'a, T, (), S, A>,
+}
+
+impl<'a, T, S, A> ParallelIterator for SetParIterMut<'a, T, S, A>
+where
+    T: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Item = &'a mut T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        self.inner.map(|(k, _)| k).drive_unindexed(consumer)
+    }
+}
+
+/// Parallel iterator that removes all elements of a `HashSet`.
+pub struct SetParDrain<'a, T, S, A: Allocator> {
+    inner: ParDrain<'a, T, (), S, A>,
+}
+
+impl<T, S, A> ParallelIterator for SetParDrain<'_, T, S, A>
+where
+    T: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Item = T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        self.inner.map(|(k, ())| k).drive_unindexed(consumer)
+    }
+}
+
+/// Parallel iterator over the elements of a `HashSet`, by value.
+pub struct SetIntoParIter<T, S, A: Allocator> {
+    inner: IntoParIter<T, (), S, A>,
+}
+
+impl<T, S, A> ParallelIterator for SetIntoParIter<T, S, A>
+where
+    T: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Item = T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        self.inner.map(|(k, ())| k).drive_unindexed(consumer)
+    }
+}
+
+impl<'a, T, S, A> IntoParallelIterator for &'a HashSet<T, S, A>
+where
+    T: Sync,
+    S: Sync,
+    A: Allocator + Sync,
+{
+    type Iter = SetParIter<'a, T, S, A>;
+    type Item = &'a T;
+
+    fn into_par_iter(self) -> Self::Iter {
+        SetParIter {
+            inner: ParIter { map: &self.map },
+        }
+    }
+}
+
+impl<'a, T, S, A> IntoParallelIterator for &'a mut HashSet<T, S, A>
+where
+    T: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Iter = SetParIterMut<'a, T, S, A>;
+    type Item = &'a mut T;
+
+    fn into_par_iter(self) -> Self::Iter {
+        SetParIterMut {
+            inner: ParIterMut { map: &mut self.map },
+        }
+    }
+}
+
+impl<T, S, A> IntoParallelIterator for HashSet<T, S, A>
+where
+    T: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Iter = SetIntoParIter<T, S, A>;
+    type Item = T;
+
+    fn into_par_iter(self) -> Self::Iter {
+        SetIntoParIter {
+            inner: IntoParIter { map: self.map },
+        }
+    }
+}
+
+impl<'a, T, S, A> ParallelDrainFull for &'a mut HashSet<T, S, A>
+where
+    T: Send,
+    S: Send,
+    A: Allocator + Send,
+{
+    type Iter = SetParDrain<'a, T, S, A>;
+    type Item = T;
+
+    fn par_drain(self) -> Self::Iter {
+        SetParDrain {
+            inner: ParDrain { map: &mut self.map },
+        }
+    }
+}
+
+impl<T, S, A> ParallelExtend<T> for HashSet<T, S, A>
+where
+    T: Hash + Eq + Send,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, par_iter: I) {
+        let (list, len) = collect(par_iter);
+        self.reserve(len);
+        for x in list.into_iter().flatten() {
+            self.insert(x);
+        }
+    }
+}
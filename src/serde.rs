@@ -0,0 +1,208 @@
+//! `Serialize` and `Deserialize` for `HashMap` and `HashSet`, behind the `serde` feature.
+//!
+//! Maps are serialized as maps and sets as sequences. `Deserialize` keeps the last of duplicate
+//! keys, like std's `HashMap`. For input where duplicate keys are a mistake (e.g. a hand-written
+//! config file), use `deny_duplicates`:
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct Config {
+//!     #[serde(deserialize_with = "hashmap::serde::deny_duplicates")]
+//!     aliases: HashMap<String, String>,
+//! }
+//! ```
+
+use core::{
+    fmt,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    mem,
+};
+
+use ::serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use allocator_api2::alloc::Allocator;
+
+use crate::{hash_map::HashMap, hash_set::HashSet};
+
+/// What deserialization does with a key that was already deserialized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Replaces the earlier entry.
+    #[default]
+    LastWins,
+    /// Fails with an error.
+    Error,
+}
+
+/// Collections that can be deserialized with a `DuplicateKeys` policy.
+pub trait DeserializeWithPolicy<'de>: Sized {
+    fn deserialize_with_policy<D>(deserializer: D, policy: DuplicateKeys) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>;
+}
+
+/// For `#[serde(deserialize_with = "...")]`, fails on duplicate keys.
+pub fn deny_duplicates<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeWithPolicy<'de>,
+{
+    T::deserialize_with_policy(deserializer, DuplicateKeys::Error)
+}
+
+/// Caps the size hint so that a malicious input can't make us allocate a lot up front.
+fn cautious<T>(hint: Option<usize>) -> usize {
+    const MAX_PREALLOC_BYTES: usize = 1024 * 1024;
+    let max = MAX_PREALLOC_BYTES / mem::size_of::<T>().max(1);
+    usize::min(hint.unwrap_or(0), max)
+}
+
+impl<K, V, S, A> Serialize for HashMap<K, V, S, A>
+where
+    K: Serialize,
+    V: Serialize,
+    A: Allocator,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (k, v) in self {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+impl<T, S, A> Serialize for HashSet<T, S, A>
+where
+    T: Serialize,
+    A: Allocator,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for x in self {
+            seq.serialize_element(x)?;
+        }
+        seq.end()
+    }
+}
+
+struct MapVisitor<K, V, S, A: Allocator> {
+    policy: DuplicateKeys,
+    _marker: PhantomData<HashMap<K, V, S, A>>,
+}
+
+impl<'de, K, V, S, A> Visitor<'de> for MapVisitor<K, V, S, A>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    type Value = HashMap<K, V, S, A>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+        let capacity = cautious::<(K, V)>(access.size_hint());
+        let mut map = HashMap::with_capacity_and_hasher_in(capacity, S::default(), A::default());
+        while let Some((k, v)) = access.next_entry()? {
+            if map.insert(k, v).is_some() && self.policy == DuplicateKeys::Error {
+                return Err(de::Error::custom("duplicate key"));
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl<'de, K, V, S, A> DeserializeWithPolicy<'de> for HashMap<K, V, S, A>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    fn deserialize_with_policy<D>(deserializer: D, policy: DuplicateKeys) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(MapVisitor {
+            policy,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<'de, K, V, S, A> Deserialize<'de> for HashMap<K, V, S, A>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::deserialize_with_policy(deserializer, DuplicateKeys::default())
+    }
+}
+
+struct SetVisitor<T, S, A: Allocator> {
+    policy: DuplicateKeys,
+    _marker: PhantomData<HashSet<T, S, A>>,
+}
+
+impl<'de, T, S, A> Visitor<'de> for SetVisitor<T, S, A>
+where
+    T: Deserialize<'de> + Hash + Eq,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    type Value = HashSet<T, S, A>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<Q: SeqAccess<'de>>(self, mut access: Q) -> Result<Self::Value, Q::Error> {
+        let capacity = cautious::<T>(access.size_hint());
+        let mut set = HashSet::with_capacity_and_hasher_in(capacity, S::default(), A::default());
+        while let Some(x) = access.next_element()? {
+            if set.insert(x).is_some() && self.policy == DuplicateKeys::Error {
+                return Err(de::Error::custom("duplicate element"));
+            }
+        }
+        Ok(set)
+    }
+}
+
+impl<'de, T, S, A> DeserializeWithPolicy<'de> for HashSet<T, S, A>
+where
+    T: Deserialize<'de> + Hash + Eq,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    fn deserialize_with_policy<D>(deserializer: D, policy: DuplicateKeys) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(SetVisitor {
+            policy,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<'de, T, S, A> Deserialize<'de> for HashSet<T, S, A>
+where
+    T: Deserialize<'de> + Hash + Eq,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::deserialize_with_policy(deserializer, DuplicateKeys::default())
+    }
+}
@@ -0,0 +1,369 @@
+//! A map that keeps a few entries inline before allocating.
+
+use core::{
+    array,
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+    slice,
+};
+
+use crate::hash_map::{self, DefaultHashBuilder, HashMap};
+
+/// Keeps up to `N` entries inline and finds them with a linear scan, which is faster than hashing
+/// for a handful of entries. Moves everything into a `HashMap` once it has more than `N` entries.
+#[derive(Clone)]
+pub struct SmallHashMap<K, V, const N: usize, S = DefaultHashBuilder> {
+    /// Entries while inline, packed at the front.
+    inline: [Option<(K, V)>; N],
+    /// Number of inline entries, or `None` once the entries have been moved into `map`.
+    inline_len: Option<usize>,
+    /// Doesn't allocate until the entries are moved in.
+    map: HashMap<K, V, S>,
+}
+
+impl<K, V, const N: usize, S> Debug for SmallHashMap<K, V, N, S>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, const N: usize> SmallHashMap<K, V, N, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+
+    /// Starts out in a `HashMap` if `capacity` is more than `N`.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, const N: usize, S> SmallHashMap<K, V, N, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Starts out in a `HashMap` if `capacity` is more than `N`.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        if capacity <= N {
+            Self {
+                inline: [const { None }; N],
+                inline_len: Some(0),
+                map: HashMap::with_hasher(hash_builder),
+            }
+        } else {
+            Self {
+                inline: [const { None }; N],
+                inline_len: None,
+                map: HashMap::with_capacity_and_hasher(capacity, hash_builder),
+            }
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// If the entries are still stored inline.
+    pub fn is_inline(&self) -> bool {
+        self.inline_len.is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inline_len.unwrap_or(self.map.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `N` while inline, the capacity of the `HashMap` after.
+    pub fn capacity(&self) -> usize {
+        match self.inline_len {
+            Some(_) => N,
+            None => self.map.capacity(),
+        }
+    }
+
+    /// Removes all entries. Once moved into the `HashMap` they stay there, keeping its buckets.
+    pub fn clear(&mut self) {
+        match self.inline_len {
+            Some(len) => {
+                self.inline[..len].fill_with(|| None);
+                self.inline_len = Some(0);
+            }
+            None => self.map.clear(),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.into_iter()
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        self.into_iter()
+    }
+}
+
+impl<K, V, const N: usize, S> Default for SmallHashMap<K, V, N, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, const N: usize, S> SmallHashMap<K, V, N, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Index of the inline entry with key `key`.
+    fn position(&self, key: &K) -> Option<usize> {
+        self.inline[..self.inline_len?]
+            .iter()
+            .position(|entry| matches!(entry, Some((k, _)) if k == key))
+    }
+
+    pub fn get_kv<'a>(&'a self, key: &K) -> Option<(&'a K, &'a V)> {
+        if !self.is_inline() {
+            return self.map.get_kv(key);
+        }
+        let (k, v) = self.inline[self.position(key)?].as_ref()?;
+        Some((k, v))
+    }
+
+    pub fn get<'a>(&'a self, key: &K) -> Option<&'a V> {
+        self.get_kv(key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut_kv<'a>(&'a mut self, key: &K) -> Option<(&'a mut K, &'a mut V)> {
+        if !self.is_inline() {
+            return self.map.get_mut_kv(key);
+        }
+        let i = self.position(key)?;
+        let (k, v) = self.inline[i].as_mut()?;
+        Some((k, v))
+    }
+
+    pub fn get_mut<'a>(&'a mut self, key: &K) -> Option<&'a mut V> {
+        self.get_mut_kv(key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_kv(key).is_some()
+    }
+
+    pub fn insert_kv(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let Some(len) = self.inline_len else {
+            return self.map.insert_kv(key, value);
+        };
+        if let Some(i) = self.position(&key) {
+            return self.inline[i].replace((key, value));
+        }
+        if len < N {
+            self.inline[len] = Some((key, value));
+            self.inline_len = Some(len + 1);
+            return None;
+        }
+        self.spill();
+        self.map.insert_kv(key, value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_kv(key, value).map(|(_, v)| v)
+    }
+
+    pub fn remove_kv(&mut self, key: &K) -> Option<(K, V)> {
+        let Some(len) = self.inline_len else {
+            return self.map.remove_kv(key);
+        };
+        let i = self.position(key)?;
+        let kv = self.inline[i].take();
+        self.inline.swap(i, len - 1);
+        self.inline_len = Some(len - 1);
+        kv
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_kv(key).map(|(_, v)| v)
+    }
+
+    /// Makes room for `additional` more entries, moving the entries into the `HashMap` if they
+    /// wouldn't fit inline.
+    pub fn reserve(&mut self, additional: usize) {
+        if let Some(len) = self.inline_len {
+            if len + additional <= N {
+                return;
+            }
+            self.spill();
+        }
+        self.map.reserve(additional);
+    }
+
+    /// Like `reserve`, but the `HashMap` doesn't reserve more than asked for.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        if let Some(len) = self.inline_len {
+            if len + additional <= N {
+                return;
+            }
+            self.spill();
+        }
+        self.map.reserve_exact(additional);
+    }
+
+    /// Shrinks the `HashMap` once the entries have been moved into it, the inline entries are
+    /// never reallocated.
+    pub fn shrink_to_fit(&mut self) {
+        if !self.is_inline() {
+            self.map.shrink_to_fit();
+        }
+    }
+
+    /// Like `shrink_to_fit`, but keeps room for at least `min_capacity` entries.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        if !self.is_inline() {
+            self.map.shrink_to(min_capacity);
+        }
+    }
+
+    /// Keeps only the entries for which `f` returns true.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let Some(len) = self.inline_len else {
+            return self.map.retain(f);
+        };
+        let mut kept = 0;
+        for i in 0..len {
+            let keep = match &mut self.inline[i] {
+                Some((k, v)) => f(k, v),
+                None => false,
+            };
+            if keep {
+                self.inline.swap(kept, i);
+                kept += 1;
+            } else {
+                self.inline[i] = None;
+            }
+        }
+        self.inline_len = Some(kept);
+    }
+
+    /// Moves the inline entries into `map`.
+    fn spill(&mut self) {
+        let Some(len) = self.inline_len.take() else {
+            return;
+        };
+        self.map.reserve(len + 1);
+        for (k, v) in self.inline[..len].iter_mut().filter_map(Option::take) {
+            self.map.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize, S> IntoIterator for &'a SmallHashMap<K, V, N, S> {
+    type Item = (&'a K, &'a V);
+
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Iter {
+            inline: self.inline.iter(),
+            map: self.map.iter(),
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize, S> IntoIterator for &'a mut SmallHashMap<K, V, N, S> {
+    type Item = (&'a mut K, &'a mut V);
+
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IterMut {
+            inline: self.inline.iter_mut(),
+            map: self.map.iter_mut(),
+        }
+    }
+}
+
+impl<K, V, const N: usize, S> IntoIterator for SmallHashMap<K, V, N, S> {
+    type Item = (K, V);
+
+    type IntoIter = IntoIter<K, V, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inline: self.inline.into_iter(),
+            map: self.map.into_iter(),
+        }
+    }
+}
+
+impl<K, V, const N: usize, S> FromIterator<(K, V)> for SmallHashMap<K, V, N, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = Self::default();
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
+
+#[derive(Clone)]
+pub struct Iter<'a, K, V> {
+    inline: slice::Iter<'a, Option<(K, V)>>,
+    map: hash_map::Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inline
+            .find_map(Option::as_ref)
+            .map(|(k, v)| (k, v))
+            .or_else(|| self.map.next())
+    }
+}
+
+pub struct IterMut<'a, K, V> {
+    inline: slice::IterMut<'a, Option<(K, V)>>,
+    map: hash_map::IterMut<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a mut K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inline
+            .find_map(Option::as_mut)
+            .map(|(k, v)| (k, v))
+            .or_else(|| self.map.next())
+    }
+}
+
+pub struct IntoIter<K, V, const N: usize> {
+    inline: array::IntoIter<Option<(K, V)>, N>,
+    map: hash_map::IntoIter<K, V>,
+}
+
+impl<K, V, const N: usize> Iterator for IntoIter<K, V, N> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inline.find_map(|kv| kv).or_else(|| self.map.next())
+    }
+}
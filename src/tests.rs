@@ -1,4 +1,6 @@
-#![cfg(test)]
+#![cfg(all(test, feature = "std"))]
+
+use std::prelude::rust_2021::*;
 
 #[allow(unused_imports)]
 use super::hash_map::*;
@@ -62,6 +64,232 @@ fn hash_collision() {
     assert_eq!(map.get(&Thing(1)), Some(&20));
 }
 
+#[test]
+fn overflow_arena() {
+    #[derive(Debug, PartialEq, Eq)]
+    struct Thing(u8);
+    impl Hash for Thing {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0u64.hash(state);
+        }
+    }
+    let mut map: HashMap<Thing, u8> = HashMap::new();
+    for i in 0..10 {
+        assert_eq!(map.insert(Thing(i), i), None);
+    }
+    assert_eq!(map.len(), 10);
+    assert_eq!(map.arena_slots(), 9);
+    assert_eq!(map.insert(Thing(5), 50), Some(5));
+    assert_eq!(map.len(), 10);
+    for i in [0, 3, 5, 9] {
+        assert!(map.remove(&Thing(i)).is_some());
+    }
+    assert_eq!(map.remove(&Thing(3)), None);
+    assert_eq!(map.len(), 6);
+    // Removed slots are reused.
+    for i in 10..14 {
+        map.insert(Thing(i), i);
+    }
+    assert_eq!(map.arena_slots(), 9);
+    for i in [1, 2, 4, 6, 7, 8, 10, 11, 12, 13] {
+        assert_eq!(map.get(&Thing(i)), Some(&i));
+    }
+    assert_eq!(map.iter().count(), 10);
+}
+
+#[test]
+fn len() {
+    let mut map: HashMap<i32, i32> = HashMap::new();
+    assert_eq!(map.remove(&1), None);
+    assert_eq!(map.len(), 0);
+    map.insert(1, 1);
+    assert_eq!(map.insert(1, 2), Some(1));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.remove(&2), None);
+    assert_eq!(map.len(), 1);
+    assert!(map.contains_key(&1) && !map.contains_key(&2));
+    assert_eq!(map.remove_kv(&1), Some((1, 2)));
+    assert!(map.is_empty());
+}
+
+#[test]
+fn treeify() {
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Thing(u32);
+    impl Hash for Thing {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0u64.hash(state);
+        }
+    }
+    let mut map: HashMap<Thing, u32> = HashMap::new();
+    for i in 0..20 {
+        map.insert(Thing(i), i);
+    }
+    assert_eq!(map.treeified_buckets(), 0);
+    map.enable_treeify();
+    assert!(map.is_treeify_enabled());
+    assert_eq!(map.treeified_buckets(), 1);
+    for i in (20..40).rev() {
+        assert_eq!(map.insert(Thing(i), i), None);
+    }
+    assert_eq!(map.insert(Thing(7), 70), Some(7));
+    map.resize(64);
+    assert_eq!(map.treeified_buckets(), 1);
+    for i in 0..40 {
+        let v = if i == 7 { 70 } else { i };
+        assert_eq!(map.get(&Thing(i)), Some(&v));
+    }
+    for i in 0..36 {
+        assert!(map.remove(&Thing(i)).is_some());
+    }
+    assert_eq!(map.remove(&Thing(0)), None);
+    assert_eq!(map.treeified_buckets(), 0);
+    assert_eq!(map.len(), 4);
+    for i in 36..40 {
+        assert_eq!(map.get(&Thing(i)), Some(&i));
+    }
+}
+
+#[test]
+fn handles() {
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Thing(u32);
+    impl Hash for Thing {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            (self.0 % 2).hash(state);
+        }
+    }
+    let mut map: HashMap<Thing, u32> = HashMap::with_capacity(64);
+    map.enable_treeify();
+    let handles: Vec<_> = (0..20)
+        .map(|i| {
+            let (handle, old) = map.insert_with_handle(Thing(i), i);
+            assert_eq!(old, None);
+            handle
+        })
+        .collect();
+    for (i, &handle) in handles.iter().enumerate() {
+        assert_eq!(
+            map.get_kv_by_handle(handle),
+            Some((&Thing(i as u32), &(i as u32)))
+        );
+    }
+    *map.get_mut_by_handle(handles[7]).unwrap() = 70;
+    assert_eq!(map.insert_with_handle(Thing(7), 71), (handles[7], Some(70)));
+    assert_eq!(map.handle(&Thing(3)), Some(handles[3]));
+    assert_eq!(map.handle(&Thing(30)), None);
+
+    // Removing an entry makes its handle stale, and the handle of the entry moved into its place.
+    // The other handles still get their own entries, also once the freed slot is reused.
+    map.remove(&Thing(0));
+    assert_eq!(map.get_by_handle(handles[0]), None);
+    map.insert(Thing(40), 40);
+    let mut stale = 0;
+    for (i, &handle) in handles.iter().enumerate() {
+        match map.get_kv_by_handle(handle) {
+            Some((k, _)) => assert_eq!(k, &Thing(i as u32)),
+            None => stale += 1,
+        }
+    }
+    assert_eq!(stale, 2);
+    assert_eq!(map.get_by_handle(handles[1]), Some(&1));
+
+    let handle = map.handle(&Thing(7)).unwrap();
+    map.clear();
+    map.insert(Thing(7), 7);
+    assert_eq!(map.get_by_handle(handle), None);
+    for i in 0..20 {
+        map.insert(Thing(i), i);
+    }
+    map.insert(Thing(7), 71);
+    let handle = map.handle(&Thing(7)).unwrap();
+    assert_eq!(map.get_by_handle(handle), Some(&71));
+    map.resize(128);
+    assert_eq!(map.get_mut_by_handle(handle), None);
+    assert_eq!(map.get_by_handle(map.handle(&Thing(7)).unwrap()), Some(&71));
+}
+
+#[test]
+fn reseed() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static RESEEDS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(PartialEq, Eq)]
+    struct Thing(u32);
+    impl Hash for Thing {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0u64.hash(state);
+        }
+    }
+    let mut map: HashMap<Thing, u32> = HashMap::new();
+    map.set_reseed_threshold(Some(4));
+    map.set_on_reseed(Some(|event| {
+        assert_eq!(event.chain_len, 5);
+        RESEEDS.fetch_add(1, Ordering::Relaxed);
+    }));
+    for i in 0..10 {
+        map.insert(Thing(i), i);
+    }
+    // Reseeding can't help with a `Hash` that ignores the seed, so it only happens once per
+    // capacity.
+    assert_eq!(map.reseed_count(), 1);
+    assert_eq!(RESEEDS.load(Ordering::Relaxed), 1);
+    assert_eq!(map.longest_chain(), 10);
+    for i in 0..10 {
+        assert_eq!(map.get(&Thing(i)), Some(&i));
+    }
+
+    let mut map: HashMap<i32, i32> = HashMap::new();
+    map.set_reseed_threshold(None);
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    assert_eq!(map.reseed_count(), 0);
+}
+
+#[test]
+fn reseed_zst() {
+    let mut map: HashMap<(), ()> = HashMap::new();
+    map.set_reseed_threshold(Some(0));
+    assert_eq!(map.insert((), ()), None);
+    assert_eq!(map.insert((), ()), Some(()));
+    assert_eq!(map.reseed_count(), 1);
+    assert_eq!(map.get(&()), Some(&()));
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn reseed_off_by_default() {
+    use std::hash::BuildHasher;
+
+    #[derive(PartialEq, Eq)]
+    struct Thing(u32);
+    impl Hash for Thing {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0u64.hash(state);
+        }
+    }
+    let mut map: HashMap<Thing, u32> = HashMap::new();
+    assert_eq!(map.reseed_threshold(), None);
+    for i in 0..100 {
+        map.insert(Thing(i), i);
+    }
+    assert_eq!(map.reseed_count(), 0);
+
+    // Keys land in the bucket their plain hash says, so the order is the same as before there
+    // was a seed.
+    let mut map: HashMap<u32, u32> = HashMap::with_capacity(1024);
+    for i in 0..10 {
+        map.insert(i, i);
+    }
+    let bucket = |k: &u32| map.hasher().hash_one(k) % 1024;
+    let mut keys: Vec<u32> = (0..10).collect();
+    keys.sort_by_key(bucket);
+    keys.dedup_by_key(|k| bucket(k));
+    assert_eq!(keys.len(), 10);
+    assert!(map.iter().map(|(k, _)| *k).eq(keys));
+}
+
 #[test]
 fn everything() {
     let mut map: HashMap<i32, i32> = HashMap::new();
@@ -93,6 +321,16 @@ fn zst() {
 }
 
 #[test]
+fn zst_resize() {
+    let mut map: HashMap<(), ()> = HashMap::new();
+    map.insert((), ());
+    map.resize(64);
+    assert_eq!(map.get(&()), Some(&()));
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+#[allow(clippy::unnecessary_sort_by)]
 fn iter() {
     let mut map: HashMap<i32, i32> = HashMap::new();
     for i in 0..10 {
@@ -122,3 +360,902 @@ fn iter() {
         assert_eq!(into_pairs[i as usize], (i, i * 2));
     }
 }
+
+#[test]
+fn custom_hasher() {
+    use std::{collections::hash_map::RandomState, hash::BuildHasher};
+    let mut map: HashMap<&str, i32, RandomState> = HashMap::with_hasher(RandomState::new());
+    map.insert("a", 1);
+    map.insert("b", 2);
+    assert_eq!(map.get(&"a"), Some(&1));
+    assert_eq!(map.get(&"b"), Some(&2));
+    assert_eq!(map.hasher().hash_one(1), map.hasher().hash_one(1));
+}
+
+#[cfg(feature = "fxhash")]
+#[test]
+fn fxhash() {
+    use crate::hashers::FxHashMap;
+    let mut map: FxHashMap<u32, u32> = FxHashMap::default();
+    for i in 0..100 {
+        map.insert(i, i * 2);
+    }
+    for i in 0..100 {
+        assert_eq!(map.get(&i), Some(&(i * 2)));
+    }
+
+    // Keys that only differ in their high bits still spread over the buckets.
+    let mut map: FxHashMap<u64, u64> = FxHashMap::default();
+    for i in 0..1000 {
+        map.insert(i << 12, i);
+    }
+    assert!(map.longest_chain() <= 8);
+    assert_eq!(map.get(&(999 << 12)), Some(&999));
+}
+
+#[cfg(feature = "fnv")]
+#[test]
+fn fnv() {
+    use crate::hashers::{FnvHashSet, FnvHasher};
+    let mut hasher = FnvHasher::default();
+    hasher.write(b"a");
+    assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+    let mut set: FnvHashSet<String> = FnvHashSet::default();
+    set.insert("hello".to_string());
+    assert!(set.get(&"hello".to_string()).is_some());
+    assert!(set.get(&"world".to_string()).is_none());
+}
+
+#[cfg(feature = "quickhash")]
+#[test]
+fn quickhash() {
+    use crate::hashers::{QuickHashMap, QuickState};
+    use std::hash::BuildHasher;
+    let state = QuickState::with_seeds(1, 2);
+    assert_eq!(state.hash_one("hello"), state.hash_one("hello"));
+    assert_ne!(state.hash_one("hello"), state.hash_one("hellp"));
+    assert_ne!(
+        state.hash_one("hello"),
+        QuickState::with_seeds(3, 4).hash_one("hello")
+    );
+    let mut map: QuickHashMap<u64, u64> = QuickHashMap::default();
+    for i in 0..100 {
+        map.insert(i, i * 2);
+    }
+    for i in 0..100 {
+        assert_eq!(map.get(&i), Some(&(i * 2)));
+    }
+}
+
+#[test]
+fn int_map() {
+    use crate::int_map::{IntMap, IntSet};
+    use std::rc::Rc;
+
+    let mut map: IntMap<u32, u32> = IntMap::new();
+    let mut reference = std::collections::HashMap::new();
+    // Deterministic pseudo-random operations, checked against std's `HashMap`.
+    let mut x: u32 = 1;
+    for _ in 0..10000 {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        let key = x % 512;
+        if x.is_multiple_of(3) {
+            assert_eq!(map.remove(&key), reference.remove(&key));
+        } else {
+            assert_eq!(map.insert(key, x), reference.insert(key, x));
+        }
+        assert_eq!(map.len(), reference.len());
+    }
+    for key in 0..512 {
+        assert_eq!(map.get(&key), reference.get(&key));
+    }
+    assert_eq!(map.iter().count(), reference.len());
+
+    // The sentinel is still a valid key.
+    assert_eq!(map.insert(u32::MAX, 1), None);
+    assert_eq!(map.get(&u32::MAX), Some(&1));
+    assert!(map.iter().any(|(k, &v)| k == u32::MAX && v == 1));
+    assert_eq!(map.remove(&u32::MAX), Some(1));
+    assert_eq!(map.get(&u32::MAX), None);
+
+    // Values are dropped exactly once.
+    let rc = Rc::new(());
+    let mut map: IntMap<u64, Rc<()>> = (0..100).map(|i| (i, rc.clone())).collect();
+    map.insert(u64::MAX, rc.clone());
+    assert_eq!(Rc::strong_count(&rc), 102);
+    map.remove(&3);
+    let mut iter = map.clone().into_iter();
+    iter.next();
+    drop(iter);
+    assert_eq!(Rc::strong_count(&rc), 101);
+    drop(map);
+    assert_eq!(Rc::strong_count(&rc), 1);
+
+    let set: IntSet<i32> = [1, 2, 3, 2, -1].into_iter().collect();
+    assert_eq!(set.len(), 4);
+    assert!(set.contains(&-1));
+    assert!(!set.contains(&4));
+}
+
+#[test]
+fn small_map() {
+    use crate::small_map::SmallHashMap;
+    let mut map: SmallHashMap<i32, i32, 4> = SmallHashMap::new();
+    for i in 0..4 {
+        assert_eq!(map.insert(i, i), None);
+    }
+    assert_eq!(map.insert(2, 20), Some(2));
+    assert!(map.is_inline());
+    assert_eq!(map.remove(&0), Some(0));
+    assert_eq!(map.remove(&0), None);
+    assert_eq!(map.get(&3), Some(&3));
+    assert_eq!(map.len(), 3);
+    map.insert(4, 4);
+    assert!(map.is_inline());
+    map.insert(5, 5);
+    assert!(!map.is_inline());
+    assert_eq!(map.len(), 5);
+    for (k, v) in [(1, 1), (2, 20), (3, 3), (4, 4), (5, 5)] {
+        assert_eq!(map.get(&k), Some(&v));
+    }
+    for (_, v) in map.iter_mut() {
+        *v += 1;
+    }
+    let mut pairs: Vec<(i32, i32)> = map.into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs, [(1, 2), (2, 21), (3, 4), (4, 5), (5, 6)]);
+}
+
+#[test]
+fn small_map_capacity() {
+    use crate::small_map::SmallHashMap;
+    let mut map: SmallHashMap<i32, i32, 4> = SmallHashMap::new();
+    assert_eq!(map.capacity(), 4);
+    map.insert(0, 0);
+    map.reserve(3);
+    assert!(map.is_inline());
+    map.reserve(4);
+    assert!(!map.is_inline());
+    assert!(map.capacity() >= 5);
+    assert_eq!(map.get(&0), Some(&0));
+    for i in 1..20 {
+        map.insert(i, i);
+    }
+    map.clear();
+    assert!(map.is_empty());
+    assert_eq!(map.get(&0), None);
+    map.insert(1, 1);
+    map.shrink_to_fit();
+    assert_eq!(map.get(&1), Some(&1));
+
+    let mut map: SmallHashMap<i32, i32, 4> = SmallHashMap::new();
+    map.insert(0, 0);
+    map.insert(1, 1);
+    map.clear();
+    assert!(map.is_inline());
+    assert!(map.is_empty());
+    assert_eq!(map.get(&0), None);
+    for i in 0..4 {
+        map.insert(i, i);
+    }
+    map.retain(|k, v| {
+        *v *= 10;
+        k % 2 == 1
+    });
+    assert!(map.is_inline());
+    assert_eq!(map.len(), 2);
+    assert_eq!(
+        (map.get(&1), map.get(&3), map.get(&2)),
+        (Some(&10), Some(&30), None)
+    );
+    map.insert(4, 4);
+    map.reserve_exact(1);
+    assert!(map.is_inline());
+    map.reserve_exact(2);
+    assert!(!map.is_inline());
+    map.retain(|k, _| *k != 1);
+    assert_eq!(map.len(), 2);
+    map.shrink_to(100);
+    assert!(map.capacity() >= 100);
+    assert_eq!(map.get(&4), Some(&4));
+
+    let map: SmallHashMap<i32, i32, 4> = SmallHashMap::with_capacity(4);
+    assert!(map.is_inline());
+    let map: SmallHashMap<i32, i32, 4> = SmallHashMap::with_capacity(5);
+    assert!(!map.is_inline());
+    assert!(map.capacity() >= 5);
+}
+
+#[test]
+fn array_map() {
+    use crate::array_map::{ArrayHashMap, ArrayHashSet};
+    let mut map: ArrayHashMap<i32, i32, 8> = ArrayHashMap::new();
+    for i in 0..8 {
+        assert_eq!(map.insert(i, i), Ok(None));
+    }
+    assert!(map.is_full());
+    assert_eq!(map.insert(8, 8), Err((8, 8)));
+    assert_eq!(map.insert(3, 30), Ok(Some(3)));
+    assert_eq!(map.remove(&0), Some(0));
+    assert_eq!(map.insert(8, 8), Ok(None));
+    assert_eq!(map.insert(0, 0), Err((0, 0)));
+    for i in 1..9 {
+        let v = if i == 3 { 30 } else { i };
+        assert_eq!(map.get(&i), Some(&v));
+    }
+    for (_, v) in &mut map {
+        *v += 1;
+    }
+    let mut pairs: Vec<(i32, i32)> = map.clone().into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs.len(), 8);
+    assert_eq!(pairs[2], (3, 31));
+    map.clear();
+    assert!(map.is_empty());
+    assert_eq!(map.iter().count(), 0);
+
+    let mut set: ArrayHashSet<&str, 2> = ArrayHashSet::new();
+    assert_eq!(set.insert("a"), Ok(None));
+    assert_eq!(set.insert("b"), Ok(None));
+    assert_eq!(set.insert("c"), Err("c"));
+    assert!(set.contains(&"a"));
+    assert_eq!(set.iter().count(), 2);
+}
+
+#[test]
+fn allocator() {
+    use crate::{hash_set::HashSet, AllocError, Allocator, Global};
+    use std::{alloc::Layout, cell::Cell, ptr::NonNull};
+
+    /// Counts live allocations.
+    #[derive(Clone, Copy)]
+    struct Counting<'a>(&'a Cell<isize>);
+    unsafe impl Allocator for Counting<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.set(self.0.get() - 1);
+            unsafe { Global.deallocate(ptr, layout) }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Thing(u32);
+    impl Hash for Thing {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0u64.hash(state);
+        }
+    }
+
+    let live = Cell::new(0);
+    let mut map = HashMap::new_in(Counting(&live));
+    map.enable_treeify();
+    for i in 0..20 {
+        map.insert(Thing(i), i);
+    }
+    // Buckets, arena and the sorted bucket.
+    assert_eq!(live.get(), 3);
+    assert_eq!(map.get(&Thing(7)), Some(&7));
+    let pairs: Vec<(Thing, u32)> = map.clone().into_iter().collect();
+    assert_eq!(pairs.len(), 20);
+    assert_eq!(live.get(), 3);
+    drop(map);
+    assert_eq!(live.get(), 0);
+
+    let mut set = HashSet::with_capacity_in(8, Counting(&live));
+    set.insert("a");
+    assert!(live.get() > 0);
+    assert_eq!(set.iter().count(), 1);
+    drop(set);
+    assert_eq!(live.get(), 0);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+    use crate::{hash_set::HashSet, serde::deny_duplicates};
+
+    let mut map: HashMap<String, u32> = HashMap::new();
+    map.insert("a".to_owned(), 1);
+    map.insert("b".to_owned(), 2);
+    let json = serde_json::to_string(&map).unwrap();
+    let de: HashMap<String, u32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(de.len(), 2);
+    assert_eq!(de.get(&"b".to_owned()), Some(&2));
+    let bytes = bincode::serialize(&map).unwrap();
+    let de: HashMap<String, u32> = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(de.get(&"a".to_owned()), Some(&1));
+
+    let json = r#"{"a": 1, "a": 2}"#;
+    let de: HashMap<String, u32> = serde_json::from_str(json).unwrap();
+    assert_eq!(de.get(&"a".to_owned()), Some(&2));
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    assert!(deny_duplicates::<_, HashMap<String, u32>>(&mut deserializer).is_err());
+
+    let set: HashSet<u8> = serde_json::from_str("[1, 2, 3]").unwrap();
+    assert_eq!(set.len(), 3);
+    assert_eq!(serde_json::to_string(&set).unwrap().len(), "[1,2,3]".len());
+    let mut deserializer = serde_json::Deserializer::from_str("[1, 1]");
+    assert!(deny_duplicates::<_, HashSet<u8>>(&mut deserializer).is_err());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn rayon() {
+    use crate::hash_set::HashSet;
+    use ::rayon::prelude::*;
+
+    let mut map: HashMap<u32, u32> = HashMap::new();
+    map.par_extend((0..10_000).into_par_iter().map(|i| (i, i)));
+    assert_eq!(map.len(), 10_000);
+    assert_eq!(
+        map.par_iter().map(|(_, &v)| v as u64).sum::<u64>(),
+        49_995_000
+    );
+    map.par_iter_mut().for_each(|(_, v)| *v *= 2);
+    assert_eq!(map.get(&4_321), Some(&8_642));
+    let capacity = map.capacity();
+    let mut drained: Vec<(u32, u32)> = map.par_drain().collect();
+    drained.sort();
+    assert_eq!(drained.len(), 10_000);
+    assert_eq!(drained[7], (7, 14));
+    assert!(map.is_empty());
+    assert_eq!(map.capacity(), capacity);
+    map.insert(1, 1);
+    assert_eq!(map.into_par_iter().count(), 1);
+
+    let mut set: HashSet<u32> = HashSet::new();
+    set.par_extend((0..1_000).into_par_iter().map(|i| i % 100));
+    assert_eq!(set.len(), 100);
+    assert_eq!(set.par_iter().filter(|&&x| x < 10).count(), 10);
+    assert_eq!(set.into_par_iter().max(), Some(99));
+}
+
+#[test]
+fn any_map() {
+    use crate::any_map::{AnyMap, SyncAnyMap};
+
+    let mut map: AnyMap = AnyMap::new();
+    assert_eq!(map.insert(1u32), None);
+    assert_eq!(map.insert("a"), None);
+    assert_eq!(map.insert(2u32), Some(1));
+    assert_eq!(map.get::<u32>(), Some(&2));
+    assert_eq!(map.get::<u64>(), None);
+    *map.get_mut::<&str>().unwrap() = "b";
+    assert_eq!(map.remove::<&str>(), Some("b"));
+    assert!(!map.contains::<&str>());
+    *map.entry::<u32>().or_insert(0) += 1;
+    *map.entry::<Vec<u8>>()
+        .and_modify(|v| v.push(0))
+        .or_default() = vec![1];
+    map.entry::<Vec<u8>>().and_modify(|v| v.push(2));
+    assert_eq!(map.get::<u32>(), Some(&3));
+    assert_eq!(map.get::<Vec<u8>>(), Some(&vec![1, 2]));
+    assert_eq!(map.len(), 2);
+
+    let mut map = SyncAnyMap::new();
+    map.insert(std::sync::Arc::new(5));
+    let map = std::thread::spawn(move || map).join().unwrap();
+    assert_eq!(**map.get::<std::sync::Arc<i32>>().unwrap(), 5);
+}
+
+#[test]
+fn bi_map() {
+    use crate::bi_map::{BiMap, Overwritten};
+
+    let mut map: BiMap<u32, String> = (0..100).map(|i| (i, i.to_string())).collect();
+    assert_eq!(map.get_by_left(&7).map(String::as_str), Some("7"));
+    assert_eq!(map.get_by_right(&"42".into()), Some(&42));
+    assert_eq!(map.insert(1_000, "x".into()), Overwritten::Neither);
+    assert_eq!(
+        map.insert(1_000, "x".into()),
+        Overwritten::Pair(1_000, "x".into())
+    );
+    assert_eq!(
+        map.insert(1_000, "y".into()),
+        Overwritten::Left(1_000, "x".into())
+    );
+    assert_eq!(
+        map.insert(1_001, "y".into()),
+        Overwritten::Right(1_000, "y".into())
+    );
+    assert_eq!(
+        map.insert(3, "5".into()),
+        Overwritten::Both((3, "3".into()), (5, "5".into()))
+    );
+    assert_eq!(map.len(), 100);
+    assert_eq!(map.insert_no_overwrite(5, "3".into()), Ok(()));
+    assert_eq!(map.insert_no_overwrite(5, "z".into()), Err((5, "z".into())));
+    assert_eq!(map.remove_by_left(&5), Some((5, "3".into())));
+    assert_eq!(map.remove_by_right(&"5".into()), Some((3, "5".into())));
+    assert!(!map.contains_left(&3) && !map.contains_right(&"3".into()));
+    assert_eq!(map.len(), 99);
+    assert!(map.iter().all(|(l, r)| map.get_by_right(r) == Some(l)));
+}
+
+#[test]
+fn cache() {
+    use crate::cache::{BoundedCache, Fifo, Lfu, Lru};
+
+    let mut lru: BoundedCache<u32, u32, Lru> = BoundedCache::new(3);
+    for i in 0..3 {
+        assert!(lru.put(i, i).is_empty());
+    }
+    assert_eq!(lru.get(&0), Some(&0));
+    assert_eq!(lru.peek(&1), Some(&1));
+    assert_eq!(lru.put(3, 3), [(1, 1)]);
+    assert_eq!(lru.put(4, 4), [(2, 2)]);
+    assert_eq!(lru.set_max_weight(1), [(0, 0), (3, 3)]);
+    assert_eq!(lru.iter().collect::<Vec<_>>(), [(&4, &4)]);
+
+    let mut fifo: BoundedCache<u32, u32, Fifo> = BoundedCache::new(2);
+    fifo.put(0, 0);
+    fifo.put(1, 1);
+    fifo.get(&0);
+    assert_eq!(fifo.put(2, 2), [(0, 0)]);
+
+    let mut lfu: BoundedCache<u32, u32, Lfu> = BoundedCache::new(3);
+    for i in 0..3 {
+        lfu.put(i, i);
+    }
+    lfu.get(&0);
+    lfu.get(&0);
+    lfu.get(&2);
+    assert_eq!(lfu.put(3, 3), [(1, 1)]);
+    assert_eq!(lfu.put(4, 4), [(3, 3)]);
+    assert_eq!(lfu.remove(&2), Some(2));
+    assert!(lfu.put(5, 5).is_empty());
+    assert_eq!(lfu.put(6, 6), [(4, 4)]);
+
+    let mut sized: BoundedCache<&str, String> = BoundedCache::with_weigher(10, |_, v| v.len());
+    sized.put("a", "12345".into());
+    sized.put("b", "1234".into());
+    assert_eq!(sized.weight(), 9);
+    assert_eq!(sized.put("c", "123".into()), [("a", "12345".into())]);
+    assert_eq!(sized.put("d", "12345678901".into()).len(), 1);
+    assert_eq!(sized.len(), 2);
+    assert_eq!(
+        sized.put("b", "12345678901".into()),
+        [("b", "12345678901".into())]
+    );
+    assert_eq!(sized.peek(&"b").map(String::as_str), Some("1234"));
+    assert_eq!(sized.weight(), 7);
+    // The replaced entry comes first, then the evicted ones.
+    assert_eq!(
+        sized.put("b", "12345678".into()),
+        [("b", "1234".into()), ("c", "123".into())]
+    );
+    assert_eq!(sized.weight(), 8);
+
+    // Weights near `usize::MAX` don't overflow.
+    let quarter = usize::MAX / 4;
+    let mut heavy: BoundedCache<u32, usize> = BoundedCache::with_weigher(usize::MAX, |_, v| *v);
+    assert!(heavy.put(0, 2 * quarter).is_empty());
+    assert_eq!(heavy.put(1, 3 * quarter), [(0, 2 * quarter)]);
+    assert_eq!(heavy.weight(), 3 * quarter);
+}
+
+#[test]
+fn concurrent_map() {
+    use crate::concurrent_map::ConcurrentHashMap;
+
+    let map: ConcurrentHashMap<u32, u32> = ConcurrentHashMap::with_shards(6);
+    assert_eq!(map.shards(), 8);
+    std::thread::scope(|s| {
+        for t in 0..4 {
+            let map = &map;
+            s.spawn(move || {
+                for i in 0..1_000 {
+                    map.insert(t * 1_000 + i, i);
+                    map.upsert(u32::MAX, 1, |n| *n += 1);
+                }
+            });
+        }
+    });
+    assert_eq!(map.len(), 4_001);
+    assert_eq!(*map.get(&u32::MAX).unwrap(), 4_000);
+    assert_eq!(map.get(&2_005).unwrap().pair(), (&2_005, &5));
+    *map.get_mut(&7).unwrap() += 10;
+    assert_eq!(map.remove(&7), Some(17));
+    map.compute(8, |_, old| old.map(|v| v * 2));
+    map.compute(9, |_, _| None);
+    map.compute(4_000, |_, old| Some(*old.unwrap_or(&42)));
+    assert_eq!(map.get(&8).as_deref(), Some(&16));
+    assert!(!map.contains_key(&9));
+    assert_eq!(map.get(&4_000).as_deref(), Some(&42));
+    let panicked = std::panic::catch_unwind(|| map.compute(8, |_, _| std::panic!()));
+    assert!(panicked.is_err());
+    assert_eq!(map.get(&8).as_deref(), Some(&16));
+    assert_eq!(map.iter().count(), 4_000);
+    assert_eq!(
+        map.iter()
+            .filter(|r| *r.key() < 1_000)
+            .map(|r| *r.value())
+            .sum::<u32>(),
+        499_500 - 7 - 9 + 8
+    );
+    map.clear();
+    assert!(map.is_empty());
+}
+
+#[test]
+fn expiring_map() {
+    use crate::expiring_map::{ExpiringHashMap, ManualClock};
+    use std::time::Duration;
+
+    let secs = Duration::from_secs;
+    let mut map: ExpiringHashMap<u32, u32, ManualClock> =
+        ExpiringHashMap::with_clock(ManualClock::new());
+    map.insert(1, 10, secs(10));
+    map.insert(2, 20, secs(20));
+    assert_eq!(map.get(&1), Some(&10));
+    map.clock().advance(secs(5));
+    assert_eq!(map.ttl(&1), Some(secs(5)));
+    map.clock().advance(secs(5));
+    assert_eq!(map.get(&1), None);
+    assert_eq!(map.iter().collect::<Vec<_>>(), [(&2, &20)]);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.insert(1, 11, secs(1)), None);
+    assert_eq!(map.insert(1, 12, secs(1)), Some(11));
+    map.clock().advance(secs(1));
+    assert_eq!(map.purge_expired(), 1);
+    assert_eq!(map.len(), 1);
+    for i in 100..200 {
+        map.insert(i, i, secs(1));
+        map.clock().advance(secs(1) / 10);
+    }
+    // Purged lazily along the way.
+    assert!(map.len() < 50);
+    assert_eq!(map.iter().count(), 9);
+    assert_eq!(map.remove(&2), None);
+}
+
+#[test]
+fn frozen_map() {
+    use crate::frozen_map::FrozenHashMap;
+
+    let map: FrozenHashMap<u32, String> = FrozenHashMap::new();
+    let zero = map.insert(0, "0".into());
+    let refs: Vec<&String> = (1..1_000).map(|i| map.insert(i, i.to_string())).collect();
+    // The references survive the map growing.
+    assert_eq!(zero, "0");
+    assert!(refs.iter().zip(1..).all(|(s, i)| **s == i.to_string()));
+    assert_eq!(map.insert(5, "x".into()), "5");
+    assert_eq!(map.get(&5).map(String::as_str), Some("5"));
+    assert_eq!(map.get(&1_000), None);
+    let nested = map.insert_with(1_000, || map.insert(1_001, "b".into()).clone() + "a");
+    assert_eq!(nested, "ba");
+    assert_eq!(map.len(), 1_002);
+    let mut map = map;
+    map.get_mut(&1).unwrap().push('!');
+    assert_eq!(map.remove(&1).as_deref(), Some("1!"));
+    assert_eq!(map.remove(&0).as_deref(), Some("0"));
+    assert!(!map.contains_key(&0));
+
+    // A panic while the map is in use doesn't leave it locked.
+    #[derive(PartialEq, Eq)]
+    struct Key(u32);
+    impl Hash for Key {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            assert_ne!(self.0, 0);
+            self.0.hash(state);
+        }
+    }
+    let map: FrozenHashMap<Key, u32> = FrozenHashMap::new();
+    map.insert(Key(1), 1);
+    let get = std::panic::AssertUnwindSafe(|| map.get(&Key(0)).is_some());
+    assert!(std::panic::catch_unwind(get).is_err());
+    assert_eq!(map.get(&Key(1)), Some(&1));
+}
+
+#[test]
+fn hash_bag() {
+    use crate::hash_bag::HashBag;
+
+    let words = "the cat and the dog and the bird";
+    let mut bag: HashBag<&str> = words.split(' ').collect();
+    assert_eq!((bag.len(), bag.distinct_len()), (8, 5));
+    assert_eq!(bag.count(&"the"), 3);
+    assert_eq!(bag.most_common(2), [(&"the", 3), (&"and", 2)]);
+    assert_eq!(bag.add("cat"), 2);
+    assert_eq!(bag.add_n("fish", 0), 0);
+    assert!(!bag.contains(&"fish"));
+    assert_eq!(bag.remove_one(&"dog"), Some(0));
+    assert_eq!(bag.remove_one(&"dog"), None);
+    assert_eq!(bag.remove_all(&"the"), 3);
+
+    let a: HashBag<char> = "aaabbc".chars().collect();
+    let b: HashBag<char> = "abbbd".chars().collect();
+    assert_eq!(a.sum(&b), "aaaabbbbbcd".chars().collect());
+    assert_eq!(a.union(&b), "aaabbbcd".chars().collect());
+    assert_eq!(a.intersection(&b), "abb".chars().collect());
+    assert_eq!(a.difference(&b), "aac".chars().collect());
+    assert_eq!(a.difference(&b).len(), 3);
+}
+
+#[test]
+fn index_map() {
+    use crate::{index_map::IndexMap, index_set::IndexSet};
+
+    let mut map: IndexMap<u32, u32> = (0..100).rev().map(|i| (i, i * 10)).collect();
+    assert_eq!(map.first(), Some((&99, &990)));
+    assert_eq!(map.get_index_of(&0), Some(99));
+    assert_eq!(map.insert(50, 0), Some(500));
+    assert_eq!(map.get_index(49), Some((&50, &0)));
+    for (k, v) in map.iter_mut().take(10) {
+        *v = *k * 10;
+    }
+    *map.get_index_mut(49).unwrap().1 = 500;
+    assert_eq!(map.get_index(49), Some((&50, &500)));
+    let (k, v) = map.get_mut_kv(&50).unwrap();
+    *v = *k * 20;
+    assert_eq!(map.get(&50), Some(&1_000));
+    map.insert(50, 0);
+    assert_eq!(map.swap_remove(&99), Some(990));
+    assert_eq!(map.get_index(0), Some((&0, &0)));
+    assert_eq!(map.shift_remove(&98), Some(980));
+    assert_eq!(map.get_index_of(&97), Some(1));
+    assert_eq!(map.get_index_of(&0), Some(0));
+    map.move_index(0, 97);
+    assert_eq!(map.last(), Some((&0, &0)));
+    assert_eq!(map.get_index_of(&97), Some(0));
+    map.move_index(97, 10);
+    assert_eq!(map.get_index_of(&0), Some(10));
+    assert_eq!(map.get_index_of(&87), Some(11));
+    map.sort_by(|k1, _, k2, _| k1.cmp(k2));
+    assert!(map.iter().map(|(k, _)| *k).eq(0..98));
+    assert!((0..98).all(|i| map.get_index_of(&i) == Some(i as usize)));
+    for i in (0..98).step_by(2) {
+        map.swap_remove(&i);
+    }
+    assert_eq!(map.len(), 49);
+    assert!((1..98)
+        .step_by(2)
+        .all(|i| map[map.get_index_of(&i).unwrap()] == i * 10));
+
+    let mut set: IndexSet<&str> = ["c", "a", "b", "a"].into_iter().collect();
+    assert!(set.iter().eq(&["c", "a", "b"]));
+    set.sort_by(|a, b| a.cmp(b));
+    assert_eq!(set.shift_remove(&"a"), Some("a"));
+    assert!(set.into_iter().eq(["b", "c"]));
+}
+
+#[test]
+fn interner() {
+    use crate::interner::{Interner, SyncInterner};
+
+    let mut interner = Interner::new();
+    let a = interner.intern("a");
+    let b = interner.intern("bc");
+    assert_ne!(a, b);
+    assert_eq!(interner.intern("a"), a);
+    assert_eq!(interner.intern(""), interner.get("").unwrap());
+    assert_eq!(interner.get("b"), None);
+    assert_eq!(interner.resolve(b), Some("bc"));
+    assert_eq!(&interner[a], "a");
+    interner.extend(
+        (0..100)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .iter()
+            .map(String::as_str),
+    );
+    assert_eq!(interner.len(), 103);
+    assert!(interner.iter().all(|(sym, s)| interner.get(s) == Some(sym)));
+    assert_eq!(interner.iter().nth(50).map(|(_, s)| s), Some("47"));
+
+    let interner = SyncInterner::new();
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for i in 0..100 {
+                    interner.intern(&i.to_string());
+                }
+            });
+        }
+    });
+    let interner = interner.into_inner();
+    assert_eq!(interner.len(), 100);
+    assert_eq!(interner.resolve(interner.get("42").unwrap()), Some("42"));
+}
+
+#[test]
+fn multi_map() {
+    use crate::multi_map::MultiMap;
+
+    let mut map: MultiMap<u32, u32> = MultiMap::new();
+    for i in 0..1_000 {
+        map.insert(i % 10, i);
+    }
+    assert_eq!(map.total_len(), 1_000);
+    assert_eq!(map.count(&3), 100);
+    assert!(map.get_all(&3).copied().eq((3..1_000).step_by(10)));
+    assert_eq!(map.get(&4), Some(&4));
+    assert_eq!(map.remove_one(&4), Some(4));
+    assert_eq!(map.get(&4), Some(&14));
+    assert_eq!(map.remove_all(&5).len(), 100);
+    assert_eq!(map.count(&5), 0);
+    assert_eq!(map.iter().count(), 899);
+    let mut groups: Vec<_> = map
+        .iter_grouped()
+        .map(|(k, values)| (*k, values.count()))
+        .collect();
+    groups.sort();
+    assert_eq!(
+        groups,
+        [
+            (0, 100),
+            (1, 100),
+            (2, 100),
+            (3, 100),
+            (4, 99),
+            (6, 100),
+            (7, 100),
+            (8, 100),
+            (9, 100)
+        ]
+    );
+}
+
+#[test]
+fn persistent_map() {
+    use crate::persistent_map::{DiffItem, PersistentHashMap};
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Thing(u32);
+    impl Hash for Thing {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            (self.0 % 100).hash(state);
+        }
+    }
+    let v1: PersistentHashMap<Thing, u32> = (0..1_000).map(|i| (Thing(i), i)).collect();
+    let v2 = v1
+        .insert(Thing(5), 50)
+        .remove(&Thing(7))
+        .insert(Thing(1_000), 0);
+    let v3 = v2.remove(&Thing(1_000)).remove(&Thing(12345));
+    assert_eq!((v1.len(), v2.len(), v3.len()), (1_000, 1_000, 999));
+    assert_eq!(v1.get(&Thing(5)), Some(&5));
+    assert_eq!(v2.get(&Thing(5)), Some(&50));
+    assert_eq!(v1.get(&Thing(7)), Some(&7));
+    assert!(!v2.contains_key(&Thing(7)));
+    assert_eq!(v3.iter().count(), 999);
+    assert!(v1.clone().ptr_eq(&v1));
+    let mut diff = v1.diff(&v2);
+    diff.sort_by_key(|item| match item {
+        DiffItem::Added(k, _) | DiffItem::Removed(k, _) => k.0,
+        DiffItem::Changed { key, .. } => key.0,
+    });
+    assert_eq!(
+        diff,
+        [
+            DiffItem::Changed {
+                key: &Thing(5),
+                old: &5,
+                new: &50
+            },
+            DiffItem::Removed(&Thing(7), &7),
+            DiffItem::Added(&Thing(1_000), &0),
+        ]
+    );
+    assert!(v2.diff(&v2.clone()).is_empty());
+    assert_eq!(v3.diff(&v2), [DiffItem::Added(&Thing(1_000), &0)]);
+}
+
+#[test]
+fn weak_value_map() {
+    use crate::weak_value_map::WeakValueHashMap;
+    use std::sync::Arc;
+
+    let mut map = WeakValueHashMap::new();
+    let values: Vec<Arc<u32>> = (0..100).map(Arc::new).collect();
+    for (i, value) in values.iter().enumerate() {
+        assert_eq!(map.insert(i as u32, value), None);
+    }
+    assert_eq!(map.get(&5).as_deref(), Some(&5));
+    let kept = values[..10].to_vec();
+    drop(values);
+    assert_eq!(map.get(&50), None);
+    assert!(!map.contains_key(&50) && map.contains_key(&9));
+    assert_eq!(map.iter().count(), 10);
+    assert_eq!(map.len(), 100);
+    // Entries whose values are dropped right away make room instead of growing the map.
+    let capacity = map.capacity();
+    for i in 100..10_000 {
+        map.insert(i, &Arc::new(i));
+    }
+    assert_eq!(map.capacity(), capacity);
+    assert!(map.iter().map(|(_, v)| *v).eq(map.iter().map(|(k, _)| *k)));
+    assert_eq!(map.remove(&3), Some(kept[3].clone()));
+    map.purge_dead();
+    assert_eq!(map.len(), 9);
+    map.shrink_to_fit();
+    assert!(map.capacity() < capacity);
+    assert_eq!(map.get(&9), Some(kept[9].clone()));
+    // With most values alive, the map grows instead of rehashing again and again.
+    let alive: Vec<Arc<u32>> = (0..1_000).map(Arc::new).collect();
+    let capacity = map.capacity();
+    for (i, value) in alive.iter().enumerate() {
+        map.insert(20_000 + i as u32, value);
+    }
+    assert!(map.capacity() > capacity);
+    assert_eq!(map.iter().count(), 1_009);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn left_right() {
+    let (mut writer, reader) = crate::left_right::new::<u32, u32>();
+    writer.insert(1, 10);
+    writer.insert(2, 20);
+    assert_eq!(writer.pending(), 2);
+    assert!(reader.enter().is_empty());
+    writer.publish();
+    assert_eq!(writer.pending(), 0);
+    {
+        let map = reader.enter();
+        let nested = reader.enter();
+        assert_eq!(map.get(&1), Some(&10));
+        assert_eq!(nested.len(), 2);
+    }
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            let reader = writer.reader();
+            s.spawn(move || {
+                for _ in 0..1_000 {
+                    let map = reader.enter();
+                    // Every published state has keys 1 and 2 and pairs of other keys.
+                    assert!(map.contains_key(&1) && map.contains_key(&2));
+                    assert_eq!(map.len() % 2, 0);
+                }
+            });
+        }
+        for i in 0..100 {
+            writer.insert(100 + i, i);
+            writer.insert(200 + i, i);
+            writer.publish();
+            writer.remove(2);
+            writer.insert(2, i);
+            writer.publish();
+        }
+    });
+    writer.clear();
+    writer.publish();
+    assert!(reader.enter().is_empty());
+}
+
+#[cfg(loom)]
+#[test]
+fn left_right_loom() {
+    loom::model(|| {
+        let (mut writer, reader) = crate::left_right::new::<u32, u32>();
+        let own_reader = writer.reader();
+        let thread = loom::thread::spawn(move || {
+            // Every publish changes both keys, a reader sees all of a state or none of it.
+            let state = |map: &crate::hash_map::HashMap<u32, u32>| {
+                let a = map.get(&1).copied();
+                assert_eq!(a, map.get(&2).copied());
+                a.unwrap_or(0)
+            };
+            let seen = state(&reader.enter());
+            let later = state(&reader.enter());
+            assert!(seen <= later);
+        });
+        writer.insert(1, 1);
+        writer.insert(2, 1);
+        writer.publish();
+        assert_eq!(own_reader.enter().get(&1), Some(&1));
+        writer.insert(1, 2);
+        writer.insert(2, 2);
+        writer.publish();
+        {
+            let map = own_reader.enter();
+            assert_eq!((map.get(&1), map.get(&2)), (Some(&2), Some(&2)));
+        }
+        thread.join().unwrap();
+    });
+}
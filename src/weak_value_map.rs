@@ -0,0 +1,188 @@
+//! A map holding its values weakly, so entries die with their values.
+
+use alloc::sync::{Arc, Weak};
+use core::{
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hash},
+};
+
+use crate::hash_map::{self, DefaultHashBuilder, HashMap};
+
+fn is_alive<V>(value: &Weak<V>) -> bool {
+    value.strong_count() > 0
+}
+
+/// A `HashMap` of `Weak<V>`, for registries that shouldn't keep their values alive. An entry whose
+/// value was dropped is hidden right away, and removed when the map is rehashed: when `insert`
+/// would grow the map, on `reserve` and `shrink_to_fit`, or on `purge_dead`. Dead entries don't
+/// count toward the load, so if enough of them died the map is rehashed without growing.
+#[derive(Clone)]
+pub struct WeakValueHashMap<K, V, S = DefaultHashBuilder> {
+    map: HashMap<K, Weak<V>, S>,
+}
+
+impl<K: Debug, V: Debug, S> Debug for WeakValueHashMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> WeakValueHashMap<K, V, DefaultHashBuilder> {
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(capacity),
+        }
+    }
+}
+
+impl<K, V, S> WeakValueHashMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hash_builder),
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_capacity_and_hasher(capacity, hash_builder),
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Includes dead entries that weren't removed yet.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Skips dead entries.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.map.iter(),
+        }
+    }
+}
+
+impl<K, V, S> Default for WeakValueHashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self {
+            map: HashMap::default(),
+        }
+    }
+}
+
+impl<K, V, S> WeakValueHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.map.get(key)?.upgrade()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.get(key).is_some_and(is_alive)
+    }
+
+    /// Inserts a weak reference to `value`, returns the old value unless it was dead.
+    pub fn insert(&mut self, key: K, value: &Arc<V>) -> Option<Arc<V>> {
+        self.map.expand_purging_if_needed(is_alive);
+        self.map.insert(key, Arc::downgrade(value))?.upgrade()
+    }
+
+    /// Returns the value unless it was dead. A dead entry is removed all the same.
+    pub fn remove(&mut self, key: &K) -> Option<Arc<V>> {
+        self.map.remove(key)?.upgrade()
+    }
+
+    /// Removes the dead entries, returns how many.
+    pub fn purge_dead(&mut self) -> usize {
+        let len = self.map.len();
+        self.map.retain(|_, value| is_alive(value));
+        len - self.map.len()
+    }
+
+    /// Number of entries that aren't dead.
+    fn live_len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let new_capacity = self.live_len() + additional;
+        if self.map.capacity() < new_capacity {
+            self.map.rebuild(new_capacity, |_, value| is_alive(value));
+        }
+    }
+
+    /// Removes the dead entries and shrinks to fit the rest.
+    pub fn shrink_to_fit(&mut self) {
+        self.purge_dead();
+        self.map.shrink_to_fit();
+    }
+}
+
+impl<'a, K, V, S> Extend<(K, &'a Arc<V>)> for WeakValueHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (K, &'a Arc<V>)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a WeakValueHashMap<K, V, S> {
+    type Item = (&'a K, Arc<V>);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the live entries of a `WeakValueHashMap`, with strong references to the values.
+pub struct Iter<'a, K, V> {
+    inner: hash_map::Iter<'a, K, Weak<V>>,
+}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, Arc<V>);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .by_ref()
+            .find_map(|(k, value)| Some((k, value.upgrade()?)))
+    }
+}